@@ -7,9 +7,20 @@ use std::{
         Formatter,
         Result as FmtResult,
     },
-    ops::RangeInclusive,
+    io::{
+        self,
+        IsTerminal,
+        Write,
+        Result as IoResult,
+    },
+    env,
+};
+use unicode_width::UnicodeWidthChar;
+use crate::{
+    Span,
+    LineIndex,
+    SpanConverter,
 };
-use crate::Span;
 
 
 /// A simple error type enum. Will probably have to write a `Display` impl for it later, but
@@ -21,7 +32,7 @@ pub enum ErrorType {
     UnclosedParen,
     UnclosedCurly,
     UnclosedSquare,
-    UnexpectedToken,
+    UnexpectedToken(String),
     UnexpectedEOF,
     LineEnding,
     VarExistsInScope,
@@ -49,8 +60,39 @@ pub enum ErrorType {
     ClassHasNoAssociated,
     ConstructorRedefined,
     ConstructorRequired,
+    CallStackOverflow(usize),
+    InvalidOpcode(u8),
+    VarUninitialized,
+    ConstWithoutValue,
+    TypeMismatch(String, String),
+    TruncatingDivision(i64, i64),
+    UnclosedComment,
+    ExpectedExpression,
+    InvalidNumberLiteral,
+    NotIterable,
+    UnclosedString,
+    UnreachableCondition,
+    DivideByZero,
+    IntegerOverflow,
+    StackUnderflow,
+    CompileUnsupported(String),
+    JumpOutOfBounds(usize),
+    InvalidLocal(u8),
+    InvalidProgramFile(String),
+    NonExhaustiveMatch,
+    InvalidPattern,
+    UndefinedEnumVariant,
+    VariantRedefined,
+    UnusedVariable(String),
+    RestParamNotLast,
+    InvalidShift(i64),
+    SuperOutsideSubclass,
+    InheritanceCycle,
+    ChainedComparison,
 }
 impl ErrorType {
+    /// The code shown in the `Error[E..]` header. Each variant has its own code, and codes never
+    /// change once they are given out, so new variants take the next unused code.
     pub fn err_num(&self)->u16 {
         use ErrorType::*;
         match self {
@@ -59,10 +101,10 @@ impl ErrorType {
             UnclosedParen=>2,
             UnclosedCurly=>3,
             UnclosedSquare=>4,
-            UnexpectedToken=>5,
+            UnexpectedToken(..)=>5,
             UnexpectedEOF=>6,
             LineEnding=>7,
-            VarExistsInScope=>7,
+            VarExistsInScope=>51,
             VarDoesNotExist=>8,
             VarUndefined=>9,
             CannotReassign=>10,
@@ -87,9 +129,51 @@ impl ErrorType {
             ClassHasNoAssociated=>29,
             ConstructorRedefined=>30,
             ConstructorRequired=>31,
+            CallStackOverflow(..)=>32,
+            InvalidOpcode(..)=>33,
+            VarUninitialized=>34,
+            ConstWithoutValue=>35,
+            TypeMismatch(..)=>36,
+            TruncatingDivision(..)=>37,
+            UnclosedComment=>38,
+            ExpectedExpression=>39,
+            InvalidNumberLiteral=>40,
+            NotIterable=>41,
+            UnclosedString=>42,
+            UnreachableCondition=>43,
+            DivideByZero=>44,
+            IntegerOverflow=>45,
+            StackUnderflow=>46,
+            CompileUnsupported(..)=>47,
+            JumpOutOfBounds(..)=>48,
+            InvalidLocal(..)=>49,
+            InvalidProgramFile(..)=>50,
+            NonExhaustiveMatch=>52,
+            InvalidPattern=>53,
+            UndefinedEnumVariant=>54,
+            VariantRedefined=>55,
+            UnusedVariable(..)=>56,
+            RestParamNotLast=>57,
+            InvalidShift(..)=>58,
+            SuperOutsideSubclass=>59,
+            InheritanceCycle=>60,
+            ChainedComparison=>61,
         }
     }
 }
+impl ErrorType {
+    /// Warnings are printed like errors, but don't stop the program
+    pub fn is_warning(&self)->bool {
+        use ErrorType::*;
+        matches!(self, TruncatingDivision(..)|UnreachableCondition|UnusedVariable(..))
+    }
+
+    /// Generic errors are usually caused by an earlier, more specific error
+    pub fn is_generic(&self)->bool {
+        use ErrorType::*;
+        matches!(self, UnexpectedToken(..)|UnexpectedEOF|LineEnding)
+    }
+}
 impl Display for ErrorType {
     fn fmt(&self, f: &mut Formatter)->FmtResult {
         use ErrorType::*;
@@ -99,7 +183,11 @@ impl Display for ErrorType {
             UnclosedParen=>write!(f,"Unclosed parenthesis"),
             UnclosedCurly=>write!(f,"Unclosed curly bracket"),
             UnclosedSquare=>write!(f,"Unclosed square bracket"),
-            UnexpectedToken=>write!(f,"Unexpected token"),
+            UnexpectedToken(found)=>if found.is_empty() {
+                write!(f,"Unexpected token")
+            } else {
+                write!(f,"Unexpected token `{}`", found)
+            },
             UnexpectedEOF=>write!(f,"Unexpected end of file"),
             LineEnding=>write!(f,"Expected a semicolon or newline"),
             VarExistsInScope=>write!(f,"Variable redefined here in this scope"),
@@ -109,7 +197,7 @@ impl Display for ErrorType {
             CannotMutate=>write!(f,"Cannot mutate this"),
             BinaryOperationNotSupported(op)=>write!(f,"Binary operation ({}) is not supported on these types", op),
             UnaryOperationNotSupported(op)=>write!(f,"Unary operation ({}) is not supported on this type", op),
-            NoField(sym)=>write!(f,"There is no field named <{:?}> on this object", sym),
+            NoField(name)=>write!(f,"There is no field named `{}` on this object", name),
             CannotCall=>write!(f,"Cannot call this data type"),
             CannotIndex=>write!(f,"Cannot index this data type"),
             ArrayOutOfBounds=>write!(f,"Array index out of bounds"),
@@ -127,6 +215,35 @@ impl Display for ErrorType {
             ClassHasNoAssociated=>write!(f,"The class has no associated function"),
             ConstructorRedefined=>write!(f,"Class constructor redefined here"),
             ConstructorRequired=>write!(f,"A constructor is required for classes with fields"),
+            CallStackOverflow(max)=>write!(f,"Maximum call stack depth of {} exceeded", max),
+            InvalidOpcode(byte)=>write!(f,"Invalid opcode `{:#04x}`", byte),
+            VarUninitialized=>write!(f,"Variable is used before it is initialized"),
+            ConstWithoutValue=>write!(f,"Constants require a value"),
+            TypeMismatch(expected, got)=>write!(f,"Mismatched types. Expected `{}`, but got `{}`", expected, got),
+            TruncatingDivision(left, right)=>write!(f,"Integer division truncates `{} / {}` to `{}`. Use a float for real division", left, right, left / right),
+            UnclosedComment=>write!(f,"Unclosed block comment"),
+            ExpectedExpression=>write!(f,"Expected an expression"),
+            InvalidNumberLiteral=>write!(f,"Invalid number literal. It is either malformed or does not fit in 64 bits"),
+            NotIterable=>write!(f,"This value can not be iterated over"),
+            UnclosedString=>write!(f,"Unclosed string"),
+            UnreachableCondition=>write!(f,"This condition was already checked, so this branch is unreachable"),
+            DivideByZero=>write!(f,"Integer division by zero"),
+            IntegerOverflow=>write!(f,"Integer overflow. The result does not fit in 64 bits"),
+            StackUnderflow=>write!(f,"Tried to pop a value off an empty stack"),
+            CompileUnsupported(what)=>write!(f,"The bytecode compiler does not support {} yet", what),
+            JumpOutOfBounds(target)=>write!(f,"Jump target {} is outside of the bytecode", target),
+            InvalidLocal(slot)=>write!(f,"Local slot {} does not exist", slot),
+            InvalidProgramFile(reason)=>write!(f,"Invalid compiled program: {}", reason),
+            NonExhaustiveMatch=>write!(f,"This match does not cover every value. Add a `_ => {{}}` arm"),
+            InvalidPattern=>write!(f,"Only literals and `Name::Value` can be used as match patterns"),
+            UndefinedEnumVariant=>write!(f,"The enum does not have this variant"),
+            VariantRedefined=>write!(f,"Enum variant redefined here"),
+            UnusedVariable(name)=>write!(f,"Variable `{}` is never used. Prefix it with `_` to silence this warning", name),
+            RestParamNotLast=>write!(f,"Only the last parameter can collect the rest of the arguments with `...`"),
+            InvalidShift(amount)=>write!(f,"Cannot shift by {}. The amount must be from 0 to 63", amount),
+            SuperOutsideSubclass=>write!(f,"`super` can only be used in the constructor or methods of a class with a parent"),
+            InheritanceCycle=>write!(f,"This class inherits from itself"),
+            ChainedComparison=>write!(f,"Comparisons can't be chained. Use parenthesis or `and`"),
         }
     }
 }
@@ -183,9 +300,9 @@ impl Error {
     }
 
     #[inline]
-    /// Create a new `UnexpectedToken` error
-    pub fn token(span: Span)->Self {
-        Self::new(span, ErrorType::UnexpectedToken)
+    /// Create a new `UnexpectedToken` error. `found` is the text of the token.
+    pub fn token(span: Span, found: String)->Self {
+        Self::new(span, ErrorType::UnexpectedToken(found))
     }
 
     #[inline]
@@ -202,7 +319,15 @@ impl Error {
         }
     }
 
-    fn print_source(source: &str, metrics: SourceMetrics, line_num_width: Option<usize>, err_msg: impl Display) {
+    /// Get the span the error is reported at. For two location errors this is the second span.
+    pub fn span(&self)->Span {
+        match self {
+            Self::Standard{span,..}|
+                Self::TwoLocation{second:span,..}=>span.clone(),
+        }
+    }
+
+    fn print_source<W: Write>(w: &mut W, source: &str, metrics: SourceMetrics, line_num_width: Option<usize>, err_msg: impl Display, colors: Colors)->IoResult<()> {
         let line_delta = metrics.end.num - metrics.start.num;
         let start_offset = metrics.start.offset;
         let end_offset = metrics.end.offset;
@@ -217,9 +342,9 @@ impl Error {
 
             // print a newline if the line doesn't have one
             if line.ends_with('\n') {
-                eprint!("{:>number_width$} │ {}", line_num, line);
+                write!(w, "{:>number_width$} │ {}", line_num, line)?;
             } else {
-                eprintln!("{:>number_width$} │ {}", line_num, line);
+                writeln!(w, "{:>number_width$} │ {}", line_num, line)?;
             }
 
             // find the difference between the start and end points. subtract one because it
@@ -229,14 +354,14 @@ impl Error {
             if start_end_delta > 1 {
                 // if the difference is more than 1 character, then line characters showing the start
                 // and end
-                eprintln!("{:>number_width$}   {:start_offset$}╰{:─>start_end_delta$}", " ", "", "╯");
+                writeln!(w, "{:>number_width$}   {:start_offset$}{}╰{:─>start_end_delta$}{}", " ", "", colors.accent, "╯", colors.reset)?;
             } else {
                 // otherwise, just print a carat to show the error location
-                eprintln!("{:>number_width$}   {:start_offset$}^", " ", "");
+                writeln!(w, "{:>number_width$}   {:start_offset$}{}^{}", " ", "", colors.accent, colors.reset)?;
             }
 
             // print the error message on another line
-            eprintln!("{:number_width$}   {:start_offset$} {}", " ", "", err_msg);
+            writeln!(w, "{:number_width$}   {:start_offset$} {}", " ", "", err_msg)?;
         } else {    // multi line error
             // get the length of the longest line number (the ending line number)
             let line_num = (metrics.end.num + 1).to_string();
@@ -247,51 +372,105 @@ impl Error {
             let line1 = &source[metrics.end.range];
 
             // print the start line and line number
-            eprint!("{:>line_num_max$} │ {}", metrics.start.num + 1,line0);
+            write!(w, "{:>line_num_max$} │ {}", metrics.start.num + 1,line0)?;
 
             // print where the error happens and the error message
-            eprintln!("{:>line_num_max$} ├─{0:─>start_offset$}╯ {}", "", err_msg);
+            writeln!(w, "{:>line_num_max$} {}├─{:─>start_offset$}╯{} {}", "", colors.accent, "", colors.reset, err_msg)?;
 
             if line_delta > 1 {
                 // if there are more than 2 lines, then print a `...` showing there are hidden
                 // lines
-                eprintln!("...");
+                writeln!(w, "...")?;
             } else {
                 // otherwise just print a blank line with no number for spacing
-                eprintln!("{:>line_num_max$} │", "");
+                writeln!(w, "{:>line_num_max$} │", "")?;
             }
 
             // print the second line and a newline if it doesn't have one
             if line1.ends_with('\n') {
-                eprint!("{:>line_num_max$} │ {}", metrics.end.num + 1, line1);
+                write!(w, "{:>line_num_max$} │ {}", metrics.end.num + 1, line1)?;
             } else {
-                eprintln!("{:>line_num_max$} │ {}", metrics.end.num + 1, line1);
+                writeln!(w, "{:>line_num_max$} │ {}", metrics.end.num + 1, line1)?;
             }
 
             // print the line characters pointing to where the error ends
-            eprintln!("{:>line_num_max$} ╰─{:─>end_offset$}", "", "╯");
+            writeln!(w, "{:>line_num_max$} {}╰─{:─>end_offset$}{}", "", colors.accent, "╯", colors.reset)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Print the `Error[E..]:` line. With a file name, it is followed by the `file:line:column`
+    /// of the span's start.
+    fn print_header<W: Write>(w: &mut W, source: &str, file: Option<&str>, span: Span, err_type: &ErrorType, colors: Colors)->IoResult<()> {
+        let kind = if err_type.is_warning() {"Warning"} else {"Error"};
+        write!(w, "{}{}[E{}]:{}", colors.accent, kind, err_type.err_num(), colors.reset)?;
+
+        // editors expect lines and columns to start at 1, and columns to count characters
+        if let Some(file) = file {
+            let start = *SpanConverter::new(source)
+                .convert_chars(source, span)
+                .start();
+
+            write!(w, " {}:{}:{}", file, start.line + 1, start.column + 1)?;
         }
+
+        writeln!(w)
     }
 
-    /// Print the error to STDERR
+    /// Print the error to STDERR. The output is colored when STDERR is a terminal and the
+    /// `NO_COLOR` environment variable is not set.
     pub fn print(&self, source: &str) {
+        self.print_stderr(source, None);
+    }
+
+    /// Print the error to STDERR like [`Self::print`], with the name of the file `source` came
+    /// from in the header
+    pub fn print_file(&self, source: &str, file: &str) {
+        self.print_stderr(source, Some(file));
+    }
+
+    fn print_stderr(&self, source: &str, file: Option<&str>) {
+        let stderr = io::stderr();
+        let color = stderr.is_terminal() && env::var_os("NO_COLOR").is_none();
+
+        // there is nowhere left to report a failed write to STDERR
+        let _ = self.write_to(source, file, &mut stderr.lock(), color);
+    }
+
+    /// Write the error to `w`, formatted the same way as [`Self::print_file`] but without color
+    pub fn print_to<W: Write>(&self, source: &str, file: Option<&str>, w: &mut W)->IoResult<()> {
+        self.write_to(source, file, w, false)
+    }
+
+    /// Render the error to a string, formatted the same way as [`Self::print_file`] but without
+    /// color
+    pub fn render(&self, source: &str, file: Option<&str>)->String {
+        let mut out = Vec::new();
+        self.write_to(source, file, &mut out, false)
+            .expect("Writing to a Vec can't fail");
+
+        return String::from_utf8(out).expect("Error output is always valid UTF-8");
+    }
+
+    fn write_to<W: Write>(&self, source: &str, file: Option<&str>, w: &mut W, color: bool)->IoResult<()> {
+        let colors = Colors::new(self.err_type(), color);
+
         match self {
             Self::Standard{err_type,span}=>{
                 // check to make sure this error fits within the source string (sanity check)
                 if span.end > source.len() {
-                    println!("Invalid source");
-                    return;
+                    return writeln!(w, "Invalid source");
                 }
 
                 let metrics = SourceMetrics::new(source, span.clone());
 
-                println!("Error[E{}]:", err_type.err_num());
-                Self::print_source(source, metrics, None, err_type);
+                Self::print_header(w, source, file, span.clone(), err_type, colors)?;
+                Self::print_source(w, source, metrics, None, err_type, colors)
             },
             Self::TwoLocation{err_type,first_msg,first,second}=>{
                 if first.end > source.len() || second.end > source.len() {
-                    println!("Invalid source");
-                    return;
+                    return writeln!(w, "Invalid source");
                 }
 
                 let first_metrics = SourceMetrics::new(source, first.clone());
@@ -302,74 +481,305 @@ impl Error {
 
                 let width = first_width.max(second_width).max(3);
 
-                println!("Error[E{}]:", err_type.err_num());
-                Self::print_source(source, first_metrics, Some(width), first_msg);
-                println!();
-                Self::print_source(source, second_metrics, Some(width), err_type);
+                Self::print_header(w, source, file, second.clone(), err_type, colors)?;
+                Self::print_source(w, source, first_metrics, Some(width), first_msg, colors)?;
+                writeln!(w)?;
+                Self::print_source(w, source, second_metrics, Some(width), err_type, colors)
             },
         }
     }
 }
 
 
-#[derive(Default)]
+/// Remove duplicate errors from a batch of errors. Only one error is kept per span, and generic
+/// errors (like `UnexpectedToken`) are replaced by more specific errors at the same span. The order
+/// of the first error at each span is kept.
+pub fn dedup_errors(errors: Vec<Error>)->Vec<Error> {
+    let mut out: Vec<Error> = Vec::new();
+
+    for err in errors {
+        let span = err.span();
+        match out.iter_mut().find(|e|e.span() == span) {
+            Some(existing)=>if existing.err_type().is_generic() && !err.err_type().is_generic() {
+                *existing = err;
+            },
+            None=>out.push(err),
+        }
+    }
+
+    return out;
+}
+
+
+/// The ANSI escape codes for highlighting the error code and the lines pointing at the source.
+/// Both are empty when printing without color.
+#[derive(Copy, Clone)]
+struct Colors {
+    accent: &'static str,
+    reset: &'static str,
+}
+impl Colors {
+    fn new(err_type: &ErrorType, color: bool)->Self {
+        if !color {
+            return Colors {accent: "", reset: ""};
+        }
+
+        let accent = if err_type.is_warning() {
+            "\x1b[1;33m"   // bold yellow
+        } else {
+            "\x1b[1;31m"   // bold red
+        };
+
+        return Colors {accent, reset: "\x1b[0m"};
+    }
+}
+
 struct SourceMetrics {
     pub start: Line,
     pub end: Line,
 }
 impl SourceMetrics {
     pub fn new(source: &str, span: Span)->Self {
-        let start = span.start;
-        let end = span.end;
-
-        let mut metrics = SourceMetrics::default();
-
-        // create a list of inclusive ranges for each line
-        let mut lines = Vec::new();
-        let mut line_start = 0;
-        for (i, c) in source.char_indices() {
-            if c=='\n' {
-                lines.push(line_start..=i);
-                line_start = i + 1;
-            }
-        }
-        // add the last line
-        lines.push(line_start..=source.len());
-
-        // find which line start and end are contained in
-        for (i, line) in lines.iter().enumerate() {
-            if line.contains(&start) {
-                metrics.start = Line {
-                    range: line.clone(),
-                    num: i,
-                    offset: start - line.start(),
-                };
-            }
-            if line.contains(&(end - 1)) {
-                metrics.end = Line {
-                    range: line.clone(),
-                    num: i,
-                    offset: end - line.start(),
-                };
-                break;
-            }
-        }
+        let index = LineIndex::new(source);
+
+        let start = index.line_col(span.start);
+        let start_range = index.line_range(start.line);
+
+        // the end is exclusive, so use the line of the last character in the span
+        let end_line = index.line_col(span.end.saturating_sub(1).max(span.start)).line;
+        let end_range = index.line_range(end_line);
 
-        return metrics;
+        // the offsets are terminal columns so the carets line up under multi-byte characters
+        return SourceMetrics {
+            start: Line {
+                offset: display_width(&source[start_range.start..], start.column),
+                range: start_range,
+                num: start.line,
+            },
+            end: Line {
+                offset: display_width(&source[end_range.start..], span.end - end_range.start),
+                range: end_range,
+                num: end_line,
+            },
+        };
     }
 }
 
 struct Line {
-    pub range: RangeInclusive<usize>,
+    /// the byte range of the line including its line ending
+    pub range: Span,
     pub num: usize,
+    /// the column in the line, counted in terminal columns
     pub offset: usize,
 }
-impl Default for Line {
-    fn default()->Self {
-        Line {
-            range: 0..=0,
-            num: 0,
-            offset: 0,
+
+
+/// The number of terminal columns the first `len` bytes of `s` take up. Control characters are
+/// counted as 0 columns.
+fn display_width(s: &str, len: usize)->usize {
+    s.char_indices()
+        .take_while(|(i, _)|*i < len)
+        .map(|(_, c)|c.width().unwrap_or(0))
+        .sum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn print_to() {
+        let source = "let x = 1\nlet y = x +\n";
+        let mut out = Vec::new();
+        Error::new(18..21, ErrorType::DivideByZero).print_to(source, None, &mut out).unwrap();
+        Error::new(8..19, ErrorType::LineEnding).print_to(source, Some("test.tl"), &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+
+        let expected = [
+            "Error[E44]:",
+            "  2 │ let y = x +",
+            "              ╰─╯",
+            "               Integer division by zero",
+            "Error[E7]: test.tl:1:9",
+            "  1 │ let x = 1",
+            "    ├─────────╯ Expected a semicolon or newline",
+            "    │",
+            "  2 │ let y = x +",
+            "    ╰─────────╯",
+        ];
+        assert_eq!(out.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn wide_characters() {
+        assert_eq!(display_width("🚀", 4), 2);
+        assert_eq!(display_width("🛸x", 5), 3);
+        assert_eq!(display_width("日本", 6), 4);
+        assert_eq!(display_width("e\u{301}", 3), 1);
+
+        // the caret lines up under the `0` after the emoji
+        let source = "let s = \"🚀🚀\" / 0\n";
+        let metrics = SourceMetrics::new(source, 21..22);
+        assert_eq!(&source[21..22], "0");
+        assert_eq!(metrics.start.offset, 17);
+        assert_eq!(metrics.end.offset, 18);
+    }
+
+    #[test]
+    fn render_and_colors() {
+        let source = "let x = 1 / 0\n";
+        let error = Error::new(8..13, ErrorType::DivideByZero);
+        let warning = Error::new(8..13, ErrorType::UnreachableCondition);
+
+        let mut printed = Vec::new();
+        error.print_to(source, None, &mut printed).unwrap();
+        let rendered = error.render(source, None);
+        assert_eq!(rendered.as_bytes(), printed);
+        assert!(!rendered.contains('\x1b'));
+
+        // errors are red and warnings are yellow, and every color is reset
+        let mut colored = Vec::new();
+        error.write_to(source, None, &mut colored, true).unwrap();
+        let colored = String::from_utf8(colored).unwrap();
+        assert!(colored.starts_with("\x1b[1;31mError[E44]:\x1b[0m"));
+        assert_eq!(colored.matches("\x1b[1;31m").count(), colored.matches("\x1b[0m").count());
+
+        let mut colored = Vec::new();
+        warning.write_to(source, None, &mut colored, true).unwrap();
+        let colored = String::from_utf8(colored).unwrap();
+        assert!(colored.starts_with("\x1b[1;33mWarning["));
+    }
+
+    #[test]
+    fn dedup() {
+        let errors = vec![
+            Error::new(0..1, ErrorType::UnexpectedEOF),
+            Error::new(2..3, ErrorType::DivideByZero),
+            Error::new(0..1, ErrorType::UnclosedParen),
+            Error::new(2..3, ErrorType::LineEnding),
+            Error::new(2..3, ErrorType::IntegerOverflow),
+            Error::new(4..5, ErrorType::LineEnding),
+        ];
+
+        let kept = dedup_errors(errors)
+            .iter()
+            .map(|err|(err.span(), err.err_type().clone()))
+            .collect::<Vec<_>>();
+
+        // generic errors are replaced, but the first specific error at a span is kept
+        assert_eq!(kept, [
+            (0..1, ErrorType::UnclosedParen),
+            (2..3, ErrorType::DivideByZero),
+            (4..5, ErrorType::LineEnding),
+        ]);
+    }
+
+    #[test]
+    fn unique_codes() {
+        use ErrorType::*;
+        // doesn't compile when a variant is missing. Add new variants to `all` below too.
+        fn listed(err: &ErrorType) {
+            match err {
+                ExpectedToken(..)|ExpectedIdent|UnclosedParen|UnclosedCurly|UnclosedSquare|
+                    UnexpectedToken(..)|UnexpectedEOF|LineEnding|VarExistsInScope|VarDoesNotExist|
+                    VarUndefined|CannotReassign|CannotMutate|BinaryOperationNotSupported(..)|
+                    UnaryOperationNotSupported(..)|NoField(..)|CannotCall|CannotIndex|
+                    ArrayOutOfBounds|InvalidIndexType|InvalidType|InvalidFunctionArgs(..)|
+                    FunctionRedefined|TooManyParams|TooManyArgs|TypeHasNoFields|FieldExists|
+                    MethodRedefined|AssociatedMethodRedefined|UndefinedClass|ClassHasNoAssociated|
+                    ConstructorRedefined|ConstructorRequired|CallStackOverflow(..)|InvalidOpcode(..)|
+                    VarUninitialized|ConstWithoutValue|TypeMismatch(..)|TruncatingDivision(..)|
+                    UnclosedComment|ExpectedExpression|InvalidNumberLiteral|NotIterable|UnclosedString|
+                    UnreachableCondition|DivideByZero|IntegerOverflow|StackUnderflow|
+                    CompileUnsupported(..)|JumpOutOfBounds(..)|InvalidLocal(..)|InvalidProgramFile(..)|
+                    NonExhaustiveMatch|InvalidPattern|UndefinedEnumVariant|VariantRedefined|
+                    UnusedVariable(..)|RestParamNotLast|InvalidShift(..)|SuperOutsideSubclass|
+                    InheritanceCycle|ChainedComparison=>{},
+            }
         }
+
+        let all = [
+            ExpectedToken(String::new()),
+            ExpectedIdent,
+            UnclosedParen,
+            UnclosedCurly,
+            UnclosedSquare,
+            UnexpectedToken(String::new()),
+            UnexpectedEOF,
+            LineEnding,
+            VarExistsInScope,
+            VarDoesNotExist,
+            VarUndefined,
+            CannotReassign,
+            CannotMutate,
+            BinaryOperationNotSupported(String::new()),
+            UnaryOperationNotSupported(String::new()),
+            NoField(String::new()),
+            CannotCall,
+            CannotIndex,
+            ArrayOutOfBounds,
+            InvalidIndexType,
+            InvalidType,
+            InvalidFunctionArgs(0, 0),
+            FunctionRedefined,
+            TooManyParams,
+            TooManyArgs,
+            TypeHasNoFields,
+            FieldExists,
+            MethodRedefined,
+            AssociatedMethodRedefined,
+            UndefinedClass,
+            ClassHasNoAssociated,
+            ConstructorRedefined,
+            ConstructorRequired,
+            CallStackOverflow(0),
+            InvalidOpcode(0),
+            VarUninitialized,
+            ConstWithoutValue,
+            TypeMismatch(String::new(), String::new()),
+            TruncatingDivision(0, 0),
+            UnclosedComment,
+            ExpectedExpression,
+            InvalidNumberLiteral,
+            NotIterable,
+            UnclosedString,
+            UnreachableCondition,
+            DivideByZero,
+            IntegerOverflow,
+            StackUnderflow,
+            CompileUnsupported(String::new()),
+            JumpOutOfBounds(0),
+            InvalidLocal(0),
+            InvalidProgramFile(String::new()),
+            NonExhaustiveMatch,
+            InvalidPattern,
+            UndefinedEnumVariant,
+            VariantRedefined,
+            UnusedVariable(String::new()),
+            RestParamNotLast,
+            InvalidShift(0),
+            SuperOutsideSubclass,
+            InheritanceCycle,
+            ChainedComparison,
+        ];
+
+        all.iter().for_each(listed);
+
+        let mut codes = all
+            .iter()
+            .map(ErrorType::err_num)
+            .collect::<Vec<_>>();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), all.len());
+
+        // codes that were given out before the duplicate was fixed keep their numbers
+        assert_eq!(LineEnding.err_num(), 7);
+        assert_eq!(VarDoesNotExist.err_num(), 8);
+        assert_eq!(ConstructorRequired.err_num(), 31);
+        assert_eq!(InvalidProgramFile(String::new()).err_num(), 50);
+        assert_eq!(VarExistsInScope.err_num(), 51);
     }
 }
@@ -32,55 +32,187 @@ impl PartialOrd for Location {
     }
 }
 
-/// Allows converting between source index spans and location spans
-pub struct SpanConverter {
-    line_spans: Vec<Span>,
+/// The start offset of every line in a source string. This is the one place that knows how to
+/// split source code into lines; everything else that needs line numbers builds on it.
+///
+/// Lines end after a `\n`, so a `\r\n` line ending is part of the line before it. Columns are
+/// byte offsets from the start of the line.
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    len: usize,
 }
-impl SpanConverter {
+impl LineIndex {
     pub fn new(source: &str)->Self {
-        let mut line_spans = Vec::new();
-        let mut prev_start = 0;
+        let mut line_starts = vec![0];
 
         for (i, c) in source.char_indices() {
-            match c {
-                '\n'=>{
-                    line_spans.push(prev_start..(i + 1));
-                    prev_start = i + 1;
-                },
-                _=>{},
+            if c == '\n' {
+                line_starts.push(i + 1);
             }
         }
-        line_spans.push(prev_start..source.len());
 
+        LineIndex {
+            line_starts,
+            len: source.len(),
+        }
+    }
+
+    /// The number of lines. Empty source still has one (empty) line.
+    pub fn line_count(&self)->usize {
+        self.line_starts.len()
+    }
+
+    /// Get the location of a byte offset. Offsets past the end of the source are clamped to the
+    /// end of the source.
+    pub fn line_col(&self, offset: usize)->Location {
+        let offset = offset.min(self.len);
+        let line = self.line_starts.partition_point(|&start|start <= offset) - 1;
+
+        Location {
+            line,
+            column: offset - self.line_starts[line],
+        }
+    }
+
+    /// Get the byte range of a line, including its line ending if it has one
+    pub fn line_range(&self, line: usize)->Span {
+        let start = self.line_starts[line];
+        let end = self.line_starts
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.len);
+
+        start..end
+    }
+
+    /// Get the byte offset of a location. This is the reverse of [`Self::line_col`]. Locations
+    /// past the end of the source are clamped to the end of the source.
+    pub fn offset(&self, location: Location)->usize {
+        match self.line_starts.get(location.line) {
+            Some(start)=>start.saturating_add(location.column).min(self.len),
+            None=>self.len,
+        }
+    }
+}
+
+/// Allows converting between source index spans and location spans
+pub struct SpanConverter {
+    index: LineIndex,
+}
+impl SpanConverter {
+    pub fn new(source: &str)->Self {
         SpanConverter {
-            line_spans,
+            index: LineIndex::new(source),
         }
     }
 
-    /// Converts a Span to a LocationSpan
+    /// Converts a Span to a LocationSpan. Spans that end at (or past) the end of the source, like
+    /// unexpected EOF errors, end on the last line.
     pub fn convert(&self, span: Span)->LocationSpan {
-        let mut start = None;
-        let mut end = None;
-        for (i, line_span) in self.line_spans.iter().enumerate() {
-            if line_span.contains(&span.start) {
-                start = Some(Location {
-                    line: i,
-                    column: span.start - line_span.start,
-                });
-            }
-            if line_span.contains(&span.end) {
-                end = Some(Location {
-                    line: i,
-                    column: span.end - line_span.start,
-                });
+        let start = self.index.line_col(span.start);
+        let end = self.index.line_col(span.end);
 
-                break;
-            }
-        }
+        return start..=end;
+    }
 
-        let start = start.unwrap();
-        let end = end.unwrap();
+    /// Converts a LocationSpan back to a Span
+    pub fn convert_back(&self, span: LocationSpan)->Span {
+        let start = self.index.offset(*span.start());
+        let end = self.index.offset(*span.end());
+
+        return start..end;
+    }
+
+    /// Converts a Span to a LocationSpan with the columns counted in characters instead of bytes.
+    /// `source` has to be the same string this converter was created from.
+    pub fn convert_chars(&self, source: &str, span: Span)->LocationSpan {
+        self.convert_columns(source, span, |prefix|prefix.chars().count())
+    }
+
+    /// Converts a Span to a LocationSpan with the columns counted in UTF-16 code units, which is
+    /// what the LSP uses. `source` has to be the same string this converter was created from.
+    pub fn convert_utf16(&self, source: &str, span: Span)->LocationSpan {
+        self.convert_columns(source, span, |prefix|prefix.encode_utf16().count())
+    }
+
+    /// Converts using byte columns, then re-measures the line prefix before each column with
+    /// `measure`
+    fn convert_columns<F: Fn(&str)->usize>(&self, source: &str, span: Span, measure: F)->LocationSpan {
+        let (mut start, mut end) = self.convert(span).into_inner();
+
+        start.column = measure(self.line_prefix(source, start));
+        end.column = measure(self.line_prefix(source, end));
 
         return start..=end;
     }
+
+    /// The part of the line before `location`. A column inside of a character is moved back to
+    /// the start of that character.
+    fn line_prefix<'a>(&self, source: &'a str, location: Location)->&'a str {
+        let line_start = self.index.line_range(location.line).start;
+        let mut end = (line_start + location.column).min(source.len());
+        while !source.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        return &source[line_start.min(end)..end];
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(line: usize, column: usize)->Location {
+        Location {line, column}
+    }
+
+    #[test]
+    fn line_index() {
+        let source = "ab\r\ncd\n\nef";
+        let index = LineIndex::new(source);
+
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_range(0), 0..4);
+        assert_eq!(index.line_range(3), 8..10);
+        assert_eq!(index.line_col(2), loc(0, 2));
+        assert_eq!(index.line_col(5), loc(1, 1));
+        assert_eq!(index.line_col(7), loc(2, 0));
+
+        // every offset goes to a location and back
+        for offset in 0..=source.len() {
+            assert_eq!(index.offset(index.line_col(offset)), offset);
+        }
+
+        let converter = SpanConverter::new(source);
+        assert_eq!(converter.convert(4..10), loc(1, 0)..=loc(3, 2));
+        assert_eq!(converter.convert_back(loc(1, 0)..=loc(3, 2)), 4..10);
+    }
+
+    #[test]
+    fn out_of_range_spans() {
+        let source = "ab\né";
+        let converter = SpanConverter::new(source);
+
+        // offsets past the end are clamped to the end of the last line
+        assert_eq!(converter.convert(2..100), loc(0, 2)..=loc(1, 2));
+        assert_eq!(converter.convert_back(loc(0, 50)..=loc(9, 0)), 5..5);
+
+        // a column in the middle of `é` is moved back to its start
+        assert_eq!(converter.convert_chars(source, 4..5), loc(1, 0)..=loc(1, 1));
+        assert_eq!(converter.convert_utf16(source, 100..200), loc(1, 1)..=loc(1, 1));
+    }
+
+    #[test]
+    fn char_and_utf16_columns() {
+        // `é` is 2 bytes and 1 UTF-16 unit, and `🦀` is 4 bytes and 2 UTF-16 units
+        let source = "let x\né🦀 = 1";
+        let converter = SpanConverter::new(source);
+        let span = 12..13;
+
+        assert_eq!(converter.convert(span.clone()), loc(1, 6)..=loc(1, 7));
+        assert_eq!(converter.convert_chars(source, span.clone()), loc(1, 2)..=loc(1, 3));
+        assert_eq!(converter.convert_utf16(source, span), loc(1, 3)..=loc(1, 4));
+    }
 }
@@ -21,21 +21,30 @@ pub enum Stmt {
         id: usize,
         permissions: Permissions,
         name: Symbol,
+        /// the class after the `:` in `class Child : Parent`
+        parent: Option<Symbol>,
         constructor: Option<Function>,
         // TODO: types
         fields: Vec<(Permissions, Symbol)>,
         methods: Vec<Function>,
         associated: Vec<Function>,
     },
+    Enum {
+        span: Span,
+        name: Symbol,
+        items: Vec<EnumItem>,
+    },
     CreateConst {
         span: Span,
         name: Symbol,
+        type_annotation: Option<TypeExpr>,
         data: Expr,
     },
     CreateVar {
         span: Span,
         var_type: Permissions,
         name: Symbol,
+        type_annotation: Option<TypeExpr>,
         data: Option<Expr>,
     },
     SetVar {
@@ -52,12 +61,35 @@ pub enum Stmt {
         span: Span,
         condition: Expr,
         body: Block,
+        /// ran when the loop finishes without a `break`
+        else_block: Option<Block>,
+    },
+    ForLoop {
+        span: Span,
+        binding: Symbol,
+        iterable: Expr,
+        body: Block,
+        /// ran when the loop finishes without a `break`
+        else_block: Option<Block>,
+    },
+    Match {
+        span: Span,
+        scrutinee: Expr,
+        /// each pattern and the block ran when the value is equal to it. Patterns are literals or
+        /// `AssociatedValue`s.
+        arms: Vec<(Expr, Block)>,
+        /// the `_` arm, ran when no pattern matches
+        default: Option<Block>,
     },
     Expression(Span, Expr),
     Return(Span, Option<Expr>),
     Continue(Span),
     Break(Span),
     Print(Span, Expr),
+    /// Like `Print`, but with a newline after the value
+    Println(Span, Expr),
+    /// A bare block that creates a new scope
+    Block(Block),
 }
 impl GetSpan for Stmt {
     fn span(&self)->Span {
@@ -66,16 +98,46 @@ impl GetSpan for Stmt {
             Function(span, _)|
                 DeleteVar(span, _)|
                 Class{span, ..}|
+                Enum{span, ..}|
                 CreateConst{span,..}|
                 CreateVar{span,..}|
                 SetVar{span,..}|
                 If{span,..}|
                 WhileLoop{span,..}|
+                ForLoop{span,..}|
+                Match{span,..}|
                 Expression(span, _)|
                 Return(span, _)|
                 Continue(span)|
                 Break(span)|
-                Print(span, _)=>span.clone(),
+                Print(span, _)|
+                Println(span, _)=>span.clone(),
+            Block(block)=>block.span(),
+        }
+    }
+}
+
+/// A variant in an enum definition. Variants are C-style integers.
+#[derive(Debug)]
+pub enum EnumItem {
+    /// one more than the previous variant, or 0 for the first variant
+    Name(Span, Symbol),
+    /// an explicit value, like `Red = 5`
+    NameValue(Span, Symbol, i64),
+}
+impl EnumItem {
+    pub fn name(&self)->Symbol {
+        match self {
+            Self::Name(_, name)|
+                Self::NameValue(_, name, _)=>*name,
+        }
+    }
+}
+impl GetSpan for EnumItem {
+    fn span(&self)->Span {
+        match self {
+            Self::Name(span, _)|
+                Self::NameValue(span, ..)=>span.clone(),
         }
     }
 }
@@ -102,6 +164,17 @@ pub enum Expr {
     Index(Span, Box<[Self;2]>),
     Object(Span, Vec<(Span, Symbol, Self)>),
     AssociatedValue(Span, Symbol, Symbol),
+    // a `...list` call argument that is expanded into multiple arguments
+    Spread(Span, Box<Self>),
+    // `condition ? then : otherwise`. Only the branch that is taken is evaluated.
+    Conditional(Span, Box<[Self;3]>),
+    // `start..end`, or `start..=end` when the bool is true
+    Range(Span, Box<[Self;2]>, bool),
+    Lambda(Span, Box<Lambda>),
+    // the parent class in a method, like `super.method()` or `super(args)` in a constructor
+    Super(Span),
+    // a string with `${...}` in it. The values are converted to strings and joined.
+    StringInterp(Span, Vec<StringPart>),
 }
 impl GetSpan for Expr {
     fn span(&self)->Span {
@@ -123,7 +196,13 @@ impl GetSpan for Expr {
                 List(span,..)|
                 Index(span,..)|
                 Object(span,..)|
-                AssociatedValue(span,..)=>span.clone(),
+                AssociatedValue(span,..)|
+                Spread(span,..)|
+                Conditional(span,..)|
+                Range(span,..)|
+                Lambda(span,..)|
+                Super(span)|
+                StringInterp(span,..)=>span.clone(),
         }
     }
 }
@@ -131,7 +210,7 @@ impl Expr {
     fn is_literal(&self)->bool {
         use Expr::*;
         match self {
-            Named(..)|String(..)|Float(..)|Integer(..)|Bool(..)|List(..)|Object(..)=>true,
+            Named(..)|Super(..)|String(..)|StringInterp(..)|Float(..)|Integer(..)|Bool(..)|List(..)|Object(..)=>true,
             _=>false,
         }
     }
@@ -153,9 +232,20 @@ impl Display for Expr {
             Copy(_, sym)=>write!(f, "copy <{:?}>", sym)?,
             Named(_, sym)=>write!(f, "<{:?}>", sym)?,
             String(_, sym)=>write!(f, "\"<{:?}>\"", sym)?,
+            StringInterp(_, parts)=>{
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        StringPart::Literal(s)=>write!(f, "{}", s.escape_default())?,
+                        StringPart::Expr(expr)=>write!(f, "${{{}}}", expr)?,
+                    }
+                }
+                write!(f, "\"")?;
+            },
             Integer(_, i)=>write!(f,"{}", i)?,
             Float(_, i)=>write!(f,"{}", i)?,
             Bool(_, b)=>write!(f,"{}", b)?,
+            Super(_)=>write!(f,"super")?,
             Ref(_, var_type, sym)=>write!(f,"ref {} <{:?}>", var_type, sym)?,
             List(_, items)=>{
                 write!(f,"[")?;
@@ -187,6 +277,11 @@ impl Display for Expr {
                 write!(f,"}}")?;
             },
             AssociatedValue(_, left, right)=>write!(f,"<{:?}>::<{:?}>", left, right)?,
+            Spread(_, inner)=>if inner.is_literal()||inner.is_trailing_expr() {
+                write!(f, "...{}", inner)?;
+            } else {
+                write!(f, "...({})", inner)?;
+            },
             BinaryOp(_, op, items)=>{
                 // parenthesize the left if it is not a literal expression
                 if items[0].is_literal() {
@@ -213,6 +308,55 @@ impl Display for Expr {
                     write!(f,")")?;
                 }
             },
+            Conditional(_, items)=>{
+                for (i, item) in items.iter().enumerate() {
+                    // add spaces if we need to and print the separator
+                    match (i, f.alternate()) {
+                        (1, true)=>write!(f, " ? ")?,
+                        (1, false)=>write!(f, "?")?,
+                        (2, true)=>write!(f, " : ")?,
+                        (2, false)=>write!(f, ":")?,
+                        _=>{},
+                    }
+
+                    // parenthesize the part if it is not a literal expression
+                    if item.is_literal() {
+                        item.fmt(f)?;
+                    } else {
+                        write!(f, "({})", item)?;
+                    }
+                }
+            },
+            Lambda(_, lambda)=>{
+                write!(f, "function(")?;
+                for (i, (_, _, name, _)) in lambda.params.iter().enumerate() {
+                    // add space if needed
+                    if i > 0 && f.alternate() {
+                        write!(f, ", ")?;
+                    } else if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    if lambda.variadic && i == lambda.params.len() - 1 {
+                        write!(f, "...")?;
+                    }
+                    write!(f, "<{:?}>", name)?;
+                }
+                write!(f, ") {{...}}")?;
+            },
+            Range(_, items, inclusive)=>{
+                // parenthesize the ends if they are not literal expressions
+                for (i, item) in items.iter().enumerate() {
+                    if i == 1 {
+                        write!(f, "{}", if *inclusive {"..="} else {".."})?;
+                    }
+
+                    if item.is_literal() {
+                        item.fmt(f)?;
+                    } else {
+                        write!(f, "({})", item)?;
+                    }
+                }
+            },
             UnaryOp(_, op, item)=>{
                 // print the operator
                 op.fmt(f)?;
@@ -316,6 +460,43 @@ pub enum BinaryOp {
     LessEqual,
     LogicAnd,
     LogicOr,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    In,
+}
+impl BinaryOp {
+    /// How tightly the operator binds. Higher binds tighter. Matches `parser::expr::Operator`.
+    pub fn precedence(&self)->u8 {
+        match self {
+            Self::LogicOr=>1,
+            Self::LogicAnd=>2,
+            Self::Equal|
+                Self::NotEqual|
+                Self::Greater|
+                Self::Less|
+                Self::GreaterEqual|
+                Self::LessEqual|
+                Self::In=>3,
+            Self::BitOr=>4,
+            Self::BitXor=>5,
+            Self::BitAnd=>6,
+            Self::Shl|
+                Self::Shr=>7,
+            Self::Add|
+                Self::Sub=>8,
+            Self::Mul|
+                Self::Div|
+                Self::Mod=>9,
+        }
+    }
+
+    /// Comparisons don't chain, so `a < b < c` needs parenthesis
+    pub fn is_comparison(&self)->bool {
+        self.precedence() == 3
+    }
 }
 impl Display for BinaryOp {
     fn fmt(&self, f: &mut Formatter)->FmtResult {
@@ -333,6 +514,12 @@ impl Display for BinaryOp {
             Self::LessEqual=>write!(f,"<="),
             Self::LogicAnd=>write!(f,"and"),
             Self::LogicOr=>write!(f,"or"),
+            Self::BitAnd=>write!(f,"&"),
+            Self::BitOr=>write!(f,"|"),
+            Self::BitXor=>write!(f,"^"),
+            Self::Shl=>write!(f,"<<"),
+            Self::Shr=>write!(f,">>"),
+            Self::In=>write!(f,"in"),
         }
     }
 }
@@ -341,12 +528,14 @@ impl Display for BinaryOp {
 pub enum UnaryOp {
     Negate,
     Not,
+    BitNot,
 }
 impl Display for UnaryOp {
     fn fmt(&self, f: &mut Formatter)->FmtResult {
         match self {
             Self::Negate=>write!(f,"-"),
             Self::Not=>write!(f,"!"),
+            Self::BitNot=>write!(f,"~"),
         }
     }
 }
@@ -423,27 +612,81 @@ pub struct Function {
     pub id: usize,
     pub span: Span,
     pub name: Symbol,
-    // TODO: types
-    pub params: Vec<(Span, Permissions, Symbol)>,
+    pub params: Vec<(Span, Permissions, Symbol, Option<TypeExpr>)>,
+    /// the last parameter is a `...rest` parameter that collects the extra arguments into a list
+    pub variadic: bool,
+    pub return_type: Option<TypeExpr>,
     pub body: Block,
 }
 impl GetSpan for Function {
     fn span(&self)->Span {self.span.clone()}
 }
 
+/// A piece of an interpolated string
+#[derive(Debug)]
+pub enum StringPart {
+    Literal(String),
+    Expr(Expr),
+}
+
+/// An anonymous function, like `function(a, b) { ... }`. Unlike a `Function`, it can use the
+/// variables of the scope it is created in.
+#[derive(Debug)]
+pub struct Lambda {
+    pub params: Vec<(Span, Permissions, Symbol, Option<TypeExpr>)>,
+    /// the last parameter is a `...rest` parameter
+    pub variadic: bool,
+    pub return_type: Option<TypeExpr>,
+    pub body: Block,
+}
+
 #[derive(Debug)]
 pub struct FunctionSignature {
     pub permissions: Permissions,
     pub func_type: FunctionType,
     pub span: Span,
     pub name: Symbol,
-    // TODO: types
-    pub params: Vec<(Span, Permissions, Symbol)>,
+    pub params: Vec<(Span, Permissions, Symbol, Option<TypeExpr>)>,
+    pub return_type: Option<TypeExpr>,
 }
 impl GetSpan for FunctionSignature {
     fn span(&self)->Span {self.span.clone()}
 }
 
+/// A type annotation on a function parameter or return value
+#[derive(Debug)]
+pub enum TypeExpr {
+    /// A plain type name like `Int` or `Person`
+    Named(Span, Symbol),
+    /// A type name with type arguments like `List<Int>`
+    Generic(Span, Symbol, Vec<Self>),
+}
+impl GetSpan for TypeExpr {
+    fn span(&self)->Span {
+        match self {
+            Self::Named(span,..)|
+                Self::Generic(span,..)=>span.clone(),
+        }
+    }
+}
+impl Display for TypeExpr {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Named(_, name)=>write!(f, "<{:?}>", name),
+            Self::Generic(_, name, args)=>{
+                write!(f, "<{:?}><", name)?;
+                if args.len() > 0 {
+                    for arg in &args[..args.len()-1] {
+                        write!(f, "{}, ", arg)?;
+                    }
+                    write!(f, "{}", args.last().unwrap())?;
+                }
+                write!(f, ">")
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Block {
     pub span: Span,
@@ -0,0 +1,609 @@
+//! Lowers the parsed AST to a bytecode [`Module`] for the VM. Only a subset of the language is
+//! supported for now: literals, enums, operators, string indexing, local variables, `print`, `if`,
+//! `while`, `for` over a range, `match`, and expression statements. Everything else returns a
+//! `CompileUnsupported` error.
+
+
+use fnv::FnvHashMap;
+use string_interner::DefaultSymbol as Symbol;
+use test_lang_vm::{
+    module_builder::ModuleBuilder,
+    Constant,
+    Module,
+    ModuleId,
+};
+use crate::{
+    ast::*,
+    error::*,
+    Span,
+};
+
+
+/// Compile `stmts` into a module with the given id and name
+pub fn compile<'a>(stmts: &[Stmt], id: ModuleId, name: &'a str)->Result<Module<'a>, Error> {
+    Session::default().compile(stmts, id, name)
+}
+
+
+/// Compiles a program one piece at a time, like in the REPL. The top level variables and enums of
+/// earlier pieces stay visible, so each module has to run on the same stack as the ones before it.
+#[derive(Default, Clone)]
+pub struct Session {
+    locals: Vec<Option<Symbol>>,
+    enums: FnvHashMap<Symbol, FnvHashMap<Symbol, i64>>,
+}
+impl Session {
+    /// The number of top level variables. This is how many values the stack holds between runs.
+    pub fn local_count(&self)->usize {
+        self.locals.len()
+    }
+
+    /// Compile the next piece of the program. If it fails, the session is unchanged.
+    pub fn compile<'a>(&mut self, stmts: &[Stmt], id: ModuleId, name: &'a str)->Result<Module<'a>, Error> {
+        let start = stmts
+            .first()
+            .map(|stmt|stmt.span().start)
+            .unwrap_or(0);
+
+        let mut compiler = Compiler {
+            builder: ModuleBuilder::new(start..start),
+            locals: self.locals.clone(),
+            enums: self.enums.clone(),
+        };
+
+        // enums can be used before they are defined
+        for stmt in stmts {
+            if let Stmt::Enum{name, items, ..} = stmt {
+                compiler.define_enum(*name, items)?;
+            }
+        }
+
+        for stmt in stmts {
+            compiler.compile_stmt(stmt)?;
+        }
+
+        self.locals = compiler.locals;
+        self.enums = compiler.enums;
+
+        return Ok(compiler.builder.finish(id, name));
+    }
+}
+
+
+struct Compiler {
+    builder: ModuleBuilder,
+    /// the name of each local slot. Between statements, the stack holds exactly these values.
+    /// Hidden locals, like the value of a `match`, have no name.
+    locals: Vec<Option<Symbol>>,
+    /// the value of each variant of each enum
+    enums: FnvHashMap<Symbol, FnvHashMap<Symbol, i64>>,
+}
+impl Compiler {
+    fn unsupported(span: Span, what: &str)->Error {
+        Error::new(span, ErrorType::CompileUnsupported(what.to_string()))
+    }
+
+    /// find the slot of the most recent local named `name`
+    fn local(&self, span: Span, name: Symbol)->Result<u8, Error> {
+        self.locals
+            .iter()
+            .rposition(|local|*local == Some(name))
+            .map(|slot|slot as u8)
+            .ok_or_else(||Error::new(span, ErrorType::VarDoesNotExist))
+    }
+
+    /// compile the value of a new local. It is left on the stack in the new slot.
+    fn declare_local(&mut self, span: Span, name: Option<Symbol>, data: &Expr)->Result<u8, Error> {
+        self.compile_expr(data)?;
+        return self.new_local(span, name);
+    }
+
+    /// turn the value on top of the stack into a new local
+    fn new_local(&mut self, span: Span, name: Option<Symbol>)->Result<u8, Error> {
+        let slot = self.locals.len();
+        if slot > u8::MAX as usize {
+            return Err(Self::unsupported(span, "more than 256 local variables"));
+        }
+
+        self.builder
+            .set_span(span)
+            .push_set_local(slot as u8);
+
+        self.locals.push(name);
+
+        return Ok(slot as u8);
+    }
+
+    /// number the variants of an enum. Variants without a value are one more than the variant
+    /// before them, starting at 0.
+    fn define_enum(&mut self, name: Symbol, items: &[EnumItem])->Result<(), Error> {
+        let mut variants = FnvHashMap::default();
+        let mut next = Some(0i64);
+
+        for item in items {
+            let value = match item {
+                EnumItem::Name(span, _)=>next
+                    .ok_or_else(||Error::new(span.clone(), ErrorType::IntegerOverflow))?,
+                EnumItem::NameValue(_, _, value)=>*value,
+            };
+
+            variants.insert(item.name(), value);
+            next = value.checked_add(1);
+        }
+
+        self.enums.insert(name, variants);
+
+        return Ok(());
+    }
+
+    /// get the constant for a literal or an enum variant. Other expressions return `None`.
+    fn constant(&self, expr: &Expr)->Result<Option<Constant>, Error> {
+        let constant = match expr {
+            Expr::Integer(_, i)=>Constant::Integer(*i),
+            Expr::Float(_, f)=>Constant::Float(*f),
+            Expr::Bool(_, b)=>Constant::Bool(*b),
+            Expr::String(_, s)=>Constant::String(s.clone()),
+            Expr::AssociatedValue(span, name, variant)=>match self.enums.get(name) {
+                Some(variants)=>{
+                    let value = variants
+                        .get(variant)
+                        .ok_or_else(||Error::new(span.clone(), ErrorType::UndefinedEnumVariant))?;
+
+                    Constant::Integer(*value)
+                },
+                None=>return Ok(None),
+            },
+            _=>return Ok(None),
+        };
+
+        return Ok(Some(constant));
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt)->Result<(), Error> {
+        match stmt {
+            Stmt::Print(span, expr)=>{
+                self.compile_expr(expr)?;
+                self.builder
+                    .set_span(span.clone())
+                    .push_print();
+            },
+            Stmt::Println(span, expr)=>{
+                self.compile_expr(expr)?;
+                self.builder
+                    .set_span(span.clone())
+                    .push_print()
+                    .push_constant(Constant::String("\n".to_string()))
+                    .push_print();
+            },
+            // the value of an expression statement is never used
+            Stmt::Expression(span, expr)=>{
+                self.compile_expr(expr)?;
+                self.builder
+                    .set_span(span.clone())
+                    .push_pop();
+            },
+            Stmt::Block(block)=>self.compile_block(block)?,
+            Stmt::CreateVar{span, name, data: Some(data), ..}|
+                Stmt::CreateConst{span, name, data, ..}=>{
+                    self.declare_local(span.clone(), Some(*name), data)?;
+                },
+            Stmt::SetVar{span, left, data} if left.len() == 1=>{
+                let slot = self.local(span.clone(), left[0])?;
+
+                self.compile_expr(data)?;
+                self.builder
+                    .set_span(span.clone())
+                    .push_set_local(slot);
+            },
+            Stmt::If{conditions, default, ..}=>{
+                let mut end_jumps = Vec::new();
+
+                for (condition, block) in conditions {
+                    self.compile_expr(condition)?;
+                    let next = self.builder
+                        .set_span(condition.span())
+                        .push_jump_if_false();
+
+                    self.compile_block(block)?;
+                    end_jumps.push(self.builder.push_jump());
+
+                    self.builder.patch_jump(next)?;
+                }
+
+                if let Some(block) = default {
+                    self.compile_block(block)?;
+                }
+
+                for jump in end_jumps {
+                    self.builder.patch_jump(jump)?;
+                }
+            },
+            // `break` is not supported yet, so the else block always runs after the loop
+            Stmt::WhileLoop{condition, body, else_block, ..}=>{
+                let start = self.builder.current_offset();
+
+                self.compile_expr(condition)?;
+                let exit = self.builder
+                    .set_span(condition.span())
+                    .push_jump_if_false();
+
+                self.compile_block(body)?;
+                self.builder.emit_jump_to(start)?;
+
+                self.builder.patch_jump(exit)?;
+
+                if let Some(block) = else_block {
+                    self.compile_block(block)?;
+                }
+            },
+            // the next value and the end of the range are kept in hidden locals, and the binding
+            // is a new local holding a copy of the next value for each iteration
+            Stmt::ForLoop{binding, iterable: Expr::Range(span, ends, inclusive), body, else_block, ..}=>{
+                let next = self.declare_local(ends[0].span(), None, &ends[0])?;
+                let end = self.declare_local(ends[1].span(), None, &ends[1])?;
+
+                let start = self.builder.current_offset();
+                self.builder
+                    .set_span(span.clone())
+                    .push_get_local(next)
+                    .push_get_local(end);
+                if *inclusive {
+                    self.builder.push_less_equal();
+                } else {
+                    self.builder.push_less();
+                }
+                let exit = self.builder.push_jump_if_false();
+
+                self.builder.push_get_local(next);
+                self.new_local(span.clone(), Some(*binding))?;
+                self.compile_block(body)?;
+                self.builder.push_pop();
+                self.locals.pop();
+
+                // stop after the end of an inclusive range, since it might be the largest integer
+                let mut last = None;
+                if *inclusive {
+                    last = Some(self.builder
+                        .set_span(span.clone())
+                        .push_get_local(next)
+                        .push_get_local(end)
+                        .push_equal()
+                        .push_jump_if_true());
+                }
+
+                self.builder
+                    .set_span(span.clone())
+                    .push_get_local(next)
+                    .push_constant(Constant::Integer(1))
+                    .push_add()
+                    .push_set_local(next)
+                    .emit_jump_to(start)?;
+
+                self.builder.patch_jump(exit)?;
+                if let Some(last) = last {
+                    self.builder.patch_jump(last)?;
+                }
+
+                if let Some(block) = else_block {
+                    self.compile_block(block)?;
+                }
+
+                // drop the next value and the end
+                self.builder
+                    .push_pop()
+                    .push_pop();
+                self.locals.truncate(next as usize);
+            },
+            // enums don't run any code. Top level enums were already defined, but nested ones
+            // are only visible after this.
+            Stmt::Enum{name, items, ..}=>self.define_enum(*name, items)?,
+            // each arm compares the value against its pattern, like an if-else chain
+            Stmt::Match{scrutinee, arms, default, ..}=>{
+                let slot = self.declare_local(scrutinee.span(), None, scrutinee)?;
+
+                let mut end_jumps = Vec::new();
+
+                for (pattern, block) in arms {
+                    let Some(constant) = self.constant(pattern)? else {
+                        return Err(Self::unsupported(pattern.span(), "class associated values"));
+                    };
+
+                    let next = self.builder
+                        .set_span(pattern.span())
+                        .push_get_local(slot)
+                        .push_constant(constant)
+                        .push_equal()
+                        .push_jump_if_false();
+
+                    self.compile_block(block)?;
+                    end_jumps.push(self.builder.push_jump());
+
+                    self.builder.patch_jump(next)?;
+                }
+
+                if let Some(block) = default {
+                    self.compile_block(block)?;
+                }
+
+                for jump in end_jumps {
+                    self.builder.patch_jump(jump)?;
+                }
+
+                // drop the value
+                self.builder.push_pop();
+                self.locals.pop();
+            },
+            _=>return Err(Self::unsupported(stmt.span(), "this statement")),
+        }
+
+        return Ok(());
+    }
+
+    fn compile_block(&mut self, block: &Block)->Result<(), Error> {
+        let scope_start = self.locals.len();
+
+        for stmt in block.body.iter() {
+            self.compile_stmt(stmt)?;
+        }
+
+        // drop the locals created in this block
+        for _ in scope_start..self.locals.len() {
+            self.builder.push_pop();
+        }
+        self.locals.truncate(scope_start);
+
+        return Ok(());
+    }
+
+    fn compile_expr(&mut self, expr: &Expr)->Result<(), Error> {
+        if let Some(constant) = self.constant(expr)? {
+            self.builder
+                .set_span(expr.span())
+                .push_constant(constant);
+
+            return Ok(());
+        }
+
+        match expr {
+            // the VM copies values, so moves and copies are the same
+            Expr::Named(span, name)|
+                Expr::Copy(span, name)=>{
+                    let slot = self.local(span.clone(), *name)?;
+                    self.builder
+                        .set_span(span.clone())
+                        .push_get_local(slot);
+
+                    return Ok(());
+                },
+            Expr::UnaryOp(span, op, inner)=>{
+                self.compile_expr(inner)?;
+
+                self.builder.set_span(span.clone());
+                match op {
+                    UnaryOp::Negate=>self.builder.push_negate(),
+                    UnaryOp::Not=>self.builder.push_not(),
+                    UnaryOp::BitNot=>self.builder.push_bit_not(),
+                };
+
+                return Ok(());
+            },
+            // `and` and `or` short circuit, so the right side is only evaluated when needed
+            Expr::BinaryOp(span, op@(BinaryOp::LogicAnd|BinaryOp::LogicOr), sides)=>{
+                self.compile_expr(&sides[0])?;
+
+                self.builder.set_span(span.clone());
+                let short_circuit = match op {
+                    BinaryOp::LogicAnd=>self.builder.push_jump_if_false(),
+                    _=>self.builder.push_jump_if_true(),
+                };
+
+                self.compile_expr(&sides[1])?;
+                let end = self.builder.push_jump();
+
+                // the jump popped the left side, so push the value it short circuited with
+                self.builder
+                    .patch_jump(short_circuit)?
+                    .set_span(span.clone())
+                    .push_constant(Constant::Bool(*op == BinaryOp::LogicOr))
+                    .patch_jump(end)?;
+
+                return Ok(());
+            },
+            Expr::BinaryOp(span, op, sides)=>{
+                self.compile_expr(&sides[0])?;
+                self.compile_expr(&sides[1])?;
+
+                self.builder.set_span(span.clone());
+                match op {
+                    BinaryOp::Add=>self.builder.push_add(),
+                    BinaryOp::Sub=>self.builder.push_sub(),
+                    BinaryOp::Mul=>self.builder.push_mul(),
+                    BinaryOp::Div=>self.builder.push_div(),
+                    BinaryOp::Mod=>self.builder.push_mod(),
+                    BinaryOp::Equal=>self.builder.push_equal(),
+                    BinaryOp::NotEqual=>self.builder.push_not_equal(),
+                    BinaryOp::Greater=>self.builder.push_greater(),
+                    BinaryOp::Less=>self.builder.push_less(),
+                    BinaryOp::GreaterEqual=>self.builder.push_greater_equal(),
+                    BinaryOp::LessEqual=>self.builder.push_less_equal(),
+                    BinaryOp::In=>self.builder.push_in(),
+                    BinaryOp::BitAnd=>self.builder.push_bit_and(),
+                    BinaryOp::BitOr=>self.builder.push_bit_or(),
+                    BinaryOp::BitXor=>self.builder.push_bit_xor(),
+                    BinaryOp::Shl=>self.builder.push_shl(),
+                    BinaryOp::Shr=>self.builder.push_shr(),
+                    BinaryOp::LogicAnd|
+                        BinaryOp::LogicOr=>unreachable!(),
+                };
+
+                return Ok(());
+            },
+            Expr::Range(span, ..)=>Err(Self::unsupported(span.clone(), "ranges outside of a `for` loop")),
+            Expr::Lambda(span, _)=>Err(Self::unsupported(span.clone(), "anonymous functions")),
+            // `concat` can only join 255 values at once, so longer strings are joined in pieces
+            Expr::StringInterp(span, parts)=>{
+                let mut count = 0;
+                for part in parts {
+                    if count == u8::MAX {
+                        self.builder
+                            .set_span(span.clone())
+                            .push_concat(count);
+                        count = 1;
+                    }
+
+                    match part {
+                        StringPart::Literal(s)=>{
+                            self.builder
+                                .set_span(span.clone())
+                                .push_constant(Constant::String(s.clone()));
+                        },
+                        StringPart::Expr(expr)=>self.compile_expr(expr)?,
+                    }
+                    count += 1;
+                }
+
+                self.builder
+                    .set_span(span.clone())
+                    .push_concat(count);
+
+                return Ok(());
+            },
+            // only the branch that is taken is evaluated
+            Expr::Conditional(_, items)=>{
+                self.compile_expr(&items[0])?;
+                let otherwise = self.builder
+                    .set_span(items[0].span())
+                    .push_jump_if_false();
+
+                self.compile_expr(&items[1])?;
+                let end = self.builder.push_jump();
+
+                self.builder.patch_jump(otherwise)?;
+                self.compile_expr(&items[2])?;
+                self.builder.patch_jump(end)?;
+
+                return Ok(());
+            },
+            Expr::Index(span, sides)=>{
+                self.compile_expr(&sides[0])?;
+                self.compile_expr(&sides[1])?;
+
+                self.builder
+                    .set_span(span.clone())
+                    .push_index();
+
+                return Ok(());
+            },
+            _=>Err(Self::unsupported(expr.span(), "this expression")),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use test_lang_vm::Value;
+    use crate::parser::Parser;
+    use super::*;
+
+    /// compile and run `source`. Returns the top level variables left on the stack.
+    fn run(source: &str)->Result<Vec<Value>, Error> {
+        let (mut parser, _) = Parser::new(source);
+        let stmts = parser.parse_file().unwrap();
+        let module = compile(&stmts, ModuleId(0), "test")?;
+
+        let mut stack = Vec::new();
+        module.run(0, 0, &mut stack)?;
+
+        return Ok(stack);
+    }
+
+    fn values(source: &str)->Vec<Value> {
+        run(source).unwrap()
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(values("let x = 1 + 2 * 3\nlet y = (1 + 2) * 3\nlet z = -x % 4\n"), [
+            Value::Integer(7),
+            Value::Integer(9),
+            Value::Integer(-3),
+        ]);
+        assert_eq!(values("let s = \"ab\" + \"c\"\nlet b = 1 < 2\nlet f = 1.5 * 2.0\n"), [
+            Value::String("abc".into()),
+            Value::Bool(true),
+            Value::Float(3.0),
+        ]);
+        assert_eq!(values("var x = 1\nset x += 2\nset x = x * 10\n"), [Value::Integer(30)]);
+    }
+
+    #[test]
+    fn print_consumes_its_value() {
+        // the printed values are popped, so only the variable is left
+        assert_eq!(values("let x = 1\nprint x\nprintln \"{x} and {x + 1}\"\nx + 1\n"), [Value::Integer(1)]);
+    }
+
+    #[test]
+    fn if_else_chains() {
+        let source = |x|format!("let x = {x}\nvar y = 0\nif x < 0 {{\nset y = 1\n}} else if x == 0 {{\nset y = 2\n}} else {{\nset y = 3\n}}\n");
+        assert_eq!(values(&source(-5))[1], Value::Integer(1));
+        assert_eq!(values(&source(0))[1], Value::Integer(2));
+        assert_eq!(values(&source(5))[1], Value::Integer(3));
+
+        // variables declared in a branch are dropped at the end of it
+        assert_eq!(values("if true {\nlet a = 1\n}\n"), []);
+    }
+
+    #[test]
+    fn while_loops() {
+        assert_eq!(values("var i = 0\nvar sum = 0\nwhile i < 5 {\nset i += 1\nset sum += i\n}\n"), [
+            Value::Integer(5),
+            Value::Integer(15),
+        ]);
+        // a loop that never runs still runs the else block
+        assert_eq!(values("var i = 10\nwhile i < 5 {\nset i += 1\n} else {\nset i = 0\n}\n"), [Value::Integer(0)]);
+    }
+
+    #[test]
+    fn short_circuiting() {
+        // the right side would divide by zero if it ran
+        assert_eq!(values("let a = false and 1 / 0 == 0\nlet b = true or 1 / 0 == 0\n"), [
+            Value::Bool(false),
+            Value::Bool(true),
+        ]);
+        assert_eq!(values("let a = true and 1 < 2\nlet b = false or 2 < 1\n"), [
+            Value::Bool(true),
+            Value::Bool(false),
+        ]);
+        assert_eq!(run("let a = true and 1 / 0 == 0\n").unwrap_err().err_type(), &ErrorType::DivideByZero);
+    }
+
+    #[test]
+    fn unsupported_statements() {
+        let err = run("function f() {\n}\n").unwrap_err();
+        assert!(matches!(err.err_type(), ErrorType::CompileUnsupported(_)));
+    }
+
+    #[test]
+    fn match_runs_the_matching_arm() {
+        let source = |x|format!("enum E {{\n    A,\n    B,\n    C,\n}}\nlet e = {x}\nvar y = 0\nmatch e {{\nE::A => {{\nset y = 1\n}}\nE::B => {{\nset y = 2\n}}\n_ => {{\nset y = 3\n}}\n}}\n");
+        assert_eq!(values(&source("E::A")), [Value::Integer(0), Value::Integer(1)]);
+        assert_eq!(values(&source("E::B")), [Value::Integer(1), Value::Integer(2)]);
+        assert_eq!(values(&source("E::C")), [Value::Integer(2), Value::Integer(3)]);
+    }
+
+    #[test]
+    fn enum_values() {
+        // variants without a value count up from the one before them
+        assert_eq!(values("enum E {\n    A,\n    B = 5,\n    C,\n}\nlet a = E::A\nlet b = E::B\nlet c = E::C\n"), [
+            Value::Integer(0),
+            Value::Integer(5),
+            Value::Integer(6),
+        ]);
+        // enums can be used before they are defined
+        assert_eq!(values("let a = E::A\nenum E {\n    A = -1,\n}\n"), [Value::Integer(-1)]);
+
+        let err = run("enum E {\n    A,\n}\nlet a = E::Missing\n").unwrap_err();
+        assert_eq!(err.err_type(), &ErrorType::UndefinedEnumVariant);
+    }
+}
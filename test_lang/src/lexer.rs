@@ -1,6 +1,8 @@
 use logos::{
     Logos,
     Lexer,
+    FilterResult,
+    Span,
 };
 use string_interner::{
     DefaultSymbol as Symbol,
@@ -11,18 +13,26 @@ use string_interner::{
 #[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(extras = StringInterner)]
 #[logos(skip "[ \t\r]")]
+#[logos(skip r"//[^\n]*")]
 pub enum Token {
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", intern_string)]
     Ident(Symbol),
     #[regex(r"[0-9][0-9_]*", parse_integer)]
+    #[regex(r"0x[0-9a-fA-F_]+", |lex|parse_radix_integer(lex, 16))]
+    #[regex(r"0o[0-7_]+", |lex|parse_radix_integer(lex, 8))]
+    #[regex(r"0b[01_]+", |lex|parse_radix_integer(lex, 2))]
     Integer(i64),
-    #[regex(r"[0-9_]+\.[0-9_]+", parse_float)]
+    // a digit is required after the `.`, so `1..2` is a range and `5.abs()` is a method call
+    #[regex(r"[0-9][0-9_]*\.[0-9][0-9_]*([eE][+-]?[0-9][0-9_]*)?", parse_float)]
+    #[regex(r"\.[0-9][0-9_]*([eE][+-]?[0-9][0-9_]*)?", parse_float)]
+    #[regex(r"[0-9][0-9_]*[eE][+-]?[0-9][0-9_]*", parse_float)]
     Float(f64),
     #[token("function", |_|Keyword::Function)]
     #[token("var", |_|Keyword::Var)]
     #[token("let", |_|Keyword::Let)]
     #[token("const", |_|Keyword::Const)]
     #[token("class", |_|Keyword::Class)]
+    #[token("enum", |_|Keyword::Enum)]
     #[token("super", |_|Keyword::Super)]
     #[token("mut", |_|Keyword::Mut)]
     #[token("set", |_|Keyword::Set)]
@@ -33,6 +43,9 @@ pub enum Token {
     #[token("if", |_|Keyword::If)]
     #[token("else", |_|Keyword::Else)]
     #[token("while", |_|Keyword::While)]
+    #[token("for", |_|Keyword::For)]
+    #[token("match", |_|Keyword::Match)]
+    #[token("in", |_|Keyword::In)]
     #[token("and", |_|Keyword::And)]
     #[token("or", |_|Keyword::Or)]
     #[token("ref", |_|Keyword::Ref)]
@@ -40,6 +53,7 @@ pub enum Token {
     #[token("break", |_|Keyword::Break)]
     #[token("continue", |_|Keyword::Continue)]
     #[token("print", |_|Keyword::Print)]
+    #[token("println", |_|Keyword::Println)]
     #[token("pub", |_|Keyword::Public)]
     #[token("constructor", |_|Keyword::Constructor)]
     Keyword(Keyword),
@@ -57,8 +71,20 @@ pub enum Token {
     SquareEnd,
     #[token("=")]
     Assign,
+    #[token("+=")]
+    AddAssign,
+    #[token("-=")]
+    SubAssign,
+    #[token("*=")]
+    MulAssign,
+    #[token("/=")]
+    DivAssign,
+    #[token("%=")]
+    ModAssign,
     #[token(":")]
     Colon,
+    #[token("?")]
+    Question,
     #[token("==")]
     Equal,
     #[token("!=")]
@@ -89,14 +115,40 @@ pub enum Token {
     Semicolon,
     #[token("!")]
     Not,
+    #[token("&")]
+    BitAnd,
+    #[token("|")]
+    BitOr,
+    #[token("^")]
+    BitXor,
+    #[token("<<")]
+    Shl,
+    #[token(">>")]
+    Shr,
+    #[token("~")]
+    BitNot,
     // we have to include whitespace here or we will get multiple newline tokens emitted if there
     // is a line containing only whitespace
     #[regex("\n[ \t\r\n]*")]
     Newline,
     #[token("\"", parse_string)]
-    String(String),
+    #[token("\"\"\"", parse_multiline_string)]
+    String(Vec<StringSegment>),
     #[token("::")]
     ColonColon,
+    #[token("=>")]
+    FatArrow,
+    #[token("->")]
+    ThinArrow,
+    #[token("...")]
+    Ellipsis,
+    #[token("..")]
+    DotDot,
+    #[token("..=")]
+    DotDotEq,
+    /// Never emitted. Block comments are skipped, or an error if they are unterminated.
+    #[token("/*", block_comment)]
+    BlockComment,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -106,6 +158,7 @@ pub enum Keyword {
     Let,
     Const,
     Class,
+    Enum,
     Super,
     Mut,
     Set,
@@ -116,6 +169,9 @@ pub enum Keyword {
     If,
     Else,
     While,
+    For,
+    In,
+    Match,
     And,
     Or,
     Ref,
@@ -123,26 +179,60 @@ pub enum Keyword {
     Break,
     Continue,
     Print,
+    Println,
     Public,
     Constructor,
 }
 
+/// A piece of a string literal. The lexer can't parse expressions, so an interpolation like
+/// `${name}` only records where its source is, and the parser parses it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringSegment {
+    Text(String),
+    /// the span of the source between `${` and `}`
+    Interpolation(Span),
+}
+
 
-fn parse_string<'a>(lex: &mut Lexer<'a, Token>)->Option<String> {
+fn parse_string<'a>(lex: &mut Lexer<'a, Token>)->Option<Vec<StringSegment>> {
+    let start = lex.span().end;
+    let source = lex.remainder();
+    let mut chars = source.char_indices();
     let mut escape = false;
-    let mut finished = false;
+    let mut segments = Vec::new();
     let mut out = String::new();
 
     // TODO: more escape sequences
-    for c in lex.remainder().chars() {
+    while let Some((i, c)) = chars.next() {
         if !escape {    // if we are not in an escape
             match c {
-                '"'=>{  // break the loop on double quote
-                    finished = true;
-                    break;
+                '"'=>{  // finish the string on double quote
+                    if !out.is_empty() || segments.is_empty() {
+                        segments.push(StringSegment::Text(out));
+                    }
+
+                    // bump the lexer past the contents and the trailing quote
+                    lex.bump(i + 1);
+                    return Some(segments);
                 },
                 // set escape if the current character is a backslash
                 '\\'=>escape = true,
+                '$' if source[i + 1..].starts_with('{')=>{
+                    let Some(len) = interpolation_len(&source[i + 2..]) else {
+                        break;
+                    };
+                    if !out.is_empty() {
+                        segments.push(StringSegment::Text(std::mem::take(&mut out)));
+                    }
+                    let expr_start = start + i + 2;
+                    segments.push(StringSegment::Interpolation(expr_start..expr_start + len));
+
+                    // skip the `{`, the expression, and the `}`
+                    let end = i + len + 3;
+                    while chars.offset() < end {
+                        chars.next();
+                    }
+                },
                 _=>out.push(c),
             }
         } else {
@@ -154,23 +244,134 @@ fn parse_string<'a>(lex: &mut Lexer<'a, Token>)->Option<String> {
                 'r'=>out.push('\r'),
                 't'=>out.push('\t'),
                 '\\'=>out.push('\\'),
+                '$'=>out.push('$'),
                 _=>{},  // ignore invalid escapes
             }
         }
+    }
+
+    // we reached EOF, so the error token spans to the end of the file
+    lex.bump(source.len());
+    return None;
+}
+
+// find the length of the expression in a `${...}`, up to the closing brace. Braces and strings in
+// the expression are skipped over, so `${f("}")}` works. Returns `None` if there is no closing brace.
+fn interpolation_len(source: &str)->Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escape = false;
+
+    for (i, c) in source.char_indices() {
+        if in_string {
+            match c {
+                _ if escape=>escape = false,
+                '\\'=>escape = true,
+                '"'=>in_string = false,
+                _=>{},
+            }
+            continue;
+        }
+
+        match c {
+            '"'=>in_string = true,
+            '{'=>depth += 1,
+            '}' if depth == 0=>return Some(i),
+            '}'=>depth -= 1,
+            _=>{},
+        }
+    }
+
+    return None;
+}
+
+// parse a triple quoted string. The contents are taken literally, including newlines and single
+// double quotes. If the opening quotes end their line, then that newline, a last line holding only
+// the closing quotes, and the indentation shared by every line are removed. If it is unterminated,
+// then the error token spans to the end of the file.
+fn parse_multiline_string<'a>(lex: &mut Lexer<'a, Token>)->Option<Vec<StringSegment>> {
+    match lex.remainder().find("\"\"\"") {
+        Some(len)=>{
+            let contents = &lex.remainder()[..len];
+            let out = match contents.strip_prefix('\n').or_else(||contents.strip_prefix("\r\n")) {
+                Some(contents)=>strip_indentation(contents),
+                None=>contents.to_string(),
+            };
+            // bump past the contents and the closing quotes
+            lex.bump(len + 3);
 
-        // bump the lexer by how many bytes c takes up
-        lex.bump(c.len_utf8());
+            Some(vec![StringSegment::Text(out)])
+        },
+        None=>{
+            lex.bump(lex.remainder().len());
+            None
+        },
     }
+}
 
-    if !finished {  // if we reached EOF, then return None
-        return None;
+// remove the whitespace prefix shared by all lines that aren't blank. Blank lines become empty, and
+// a blank last line is the indentation of the closing quotes, so it is removed.
+fn strip_indentation(contents: &str)->String {
+    let mut lines = contents
+        .split('\n')
+        .map(|line|line.strip_suffix('\r').unwrap_or(line))
+        .collect::<Vec<_>>();
+    if lines.last().is_some_and(|line|line.trim().is_empty()) {
+        lines.pop();
     }
 
-    // bump the lexer past the trailing quote
-    lex.bump(1);
+    let mut prefix: Option<&str> = None;
+    for line in lines.iter().filter(|line|!line.trim().is_empty()) {
+        let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+        let shared = match prefix {
+            Some(prefix)=>prefix
+                .bytes()
+                .zip(indent.bytes())
+                .take_while(|(a, b)|a == b)
+                .count(),
+            None=>indent.len(),
+        };
+        prefix = Some(&indent[..shared]);
+    }
+    let prefix = prefix.unwrap_or_default();
 
-    // intern the string, because using `Symbol` is easier than `&'a str` and faster than `String`
-    return Some(out);
+    return lines
+        .iter()
+        .map(|line|line.strip_prefix(prefix).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+// skip a (possibly nested) block comment. If it is unterminated, then the error token spans from
+// the opening `/*` to the end of the file.
+fn block_comment<'a>(lex: &mut Lexer<'a, Token>)->FilterResult<(), ()> {
+    let mut depth = 1;
+    let mut bytes = lex.remainder().bytes();
+    let mut len = 0;
+
+    while let Some(b) = bytes.next() {
+        len += 1;
+        match (b, lex.remainder().as_bytes().get(len)) {
+            (b'/', Some(b'*'))=>{
+                bytes.next();
+                len += 1;
+                depth += 1;
+            },
+            (b'*', Some(b'/'))=>{
+                bytes.next();
+                len += 1;
+                depth -= 1;
+                if depth == 0 {
+                    lex.bump(len);
+                    return FilterResult::Skip;
+                }
+            },
+            _=>{},
+        }
+    }
+
+    lex.bump(len);
+    return FilterResult::Error(());
 }
 
 // intern the string slice of the current token and return the symbol
@@ -178,18 +379,160 @@ fn intern_string<'a>(lex: &mut Lexer<'a, Token>)->Symbol {
     lex.extras.get_or_intern(lex.slice())
 }
 
-// parse an f64 from the current token's string slice
-fn parse_float<'a>(lex: &mut Lexer<'a, Token>)->f64 {
+// parse an f64 from the current token's string slice. Returns `None` for malformed floats like
+// `1.2.3`, with the error token covering the whole literal.
+fn parse_float<'a>(lex: &mut Lexer<'a, Token>)->Option<f64> {
+    let rest = lex.remainder();
+    if rest.starts_with('.') && rest[1..].starts_with(|c: char|c.is_ascii_digit()) {
+        let extra = rest
+            .bytes()
+            .take_while(|b|b.is_ascii_digit() || *b == b'.' || *b == b'_')
+            .count();
+        lex.bump(extra);
+        return None;
+    }
+
     lex
         .slice()
+        .replace('_', "")
         .parse::<f64>()
-        .unwrap()
+        .ok()
 }
 
-// parse a i64 from the current token's string slice
-fn parse_integer<'a>(lex: &mut Lexer<'a, Token>)->i64 {
+// parse a i64 from the current token's string slice. Returns `None` if it overflows.
+fn parse_integer<'a>(lex: &mut Lexer<'a, Token>)->Option<i64> {
     lex
         .slice()
+        .replace('_', "")
         .parse::<i64>()
-        .unwrap()
+        .ok()
+}
+
+// parse a i64 with a 2 character prefix like `0x` from the current token's string slice. Returns
+// `None` if it overflows.
+fn parse_radix_integer<'a>(lex: &mut Lexer<'a, Token>, radix: u32)->Option<i64> {
+    let digits = lex.slice()[2..].replace('_', "");
+
+    i64::from_str_radix(&digits, radix).ok()
+}
+
+/// Compute the bracket depth at the end of each line for editor auto-indent. Returns a
+/// `(line, depth)` pair for every line in `source`, with zero-based line numbers. Brackets inside
+/// strings and comments don't count since we use the lexer to find them. Extra closing brackets
+/// make the depth negative.
+pub fn bracket_depths(source: &str)->Vec<(usize, i32)> {
+    // the byte offset of every newline, used to find which line a token is on
+    let newlines = source
+        .char_indices()
+        .filter(|(_, c)|*c == '\n')
+        .map(|(i, _)|i)
+        .collect::<Vec<_>>();
+
+    let mut depths = vec![0; newlines.len() + 1];
+    let mut depth = 0i32;
+    let mut line = 0;
+
+    for (token, span) in Token::lexer(source).spanned() {
+        let token_line = newlines.partition_point(|&i|i < span.start);
+
+        // lines without any brackets keep the depth of the previous line
+        while line < token_line {
+            depths[line] = depth;
+            line += 1;
+        }
+
+        match token {
+            Ok(Token::ParenStart|Token::CurlyStart|Token::SquareStart)=>depth += 1,
+            Ok(Token::ParenEnd|Token::CurlyEnd|Token::SquareEnd)=>depth -= 1,
+            _=>{},
+        }
+    }
+
+    while line < depths.len() {
+        depths[line] = depth;
+        line += 1;
+    }
+
+    return depths
+        .into_iter()
+        .enumerate()
+        .collect();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrows() {
+        let tokens = Token::lexer("=>->= >-")
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(tokens, [
+            Token::FatArrow,
+            Token::ThinArrow,
+            Token::Assign,
+            Token::Greater,
+            Token::Sub,
+        ]);
+
+        // the longest token wins, so `=>=` is `=>` then `=`, and `>>=` is `>>` then `=`
+        let tokens = Token::lexer(">= =>= >=> ->> =>> >>= ==>")
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(tokens, [
+            Token::GreaterEqual,
+            Token::FatArrow,
+            Token::Assign,
+            Token::GreaterEqual,
+            Token::Greater,
+            Token::ThinArrow,
+            Token::Greater,
+            Token::FatArrow,
+            Token::Greater,
+            Token::Shr,
+            Token::Assign,
+            Token::Equal,
+            Token::Greater,
+        ]);
+    }
+
+    #[test]
+    fn numbers_before_dots() {
+        let tokens = Token::lexer("1..2 5.abs 1.5")
+            .map(|token|token.unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(matches!(tokens[..], [
+            Token::Integer(1),
+            Token::DotDot,
+            Token::Integer(2),
+            Token::Integer(5),
+            Token::Dot,
+            Token::Ident(_),
+            Token::Float(f),
+        ] if f == 1.5));
+    }
+
+    #[test]
+    fn depths() {
+        let source = "function f() {\n    let x = [\n        \"{\",\n    ]\n}\n";
+
+        assert_eq!(bracket_depths(source), [(0, 1), (1, 2), (2, 2), (3, 1), (4, 0), (5, 0)]);
+    }
+
+    #[test]
+    fn extra_closing_brackets() {
+        assert_eq!(bracket_depths(")]\n{"), [(0, -2), (1, -1)]);
+    }
+
+    #[test]
+    fn brackets_in_strings_and_comments() {
+        let source = "let s = \"}\" // )\n[ // {\n\"(\"\n]\n";
+
+        assert_eq!(bracket_depths(source), [(0, 0), (1, 1), (2, 1), (3, 0), (4, 0)]);
+    }
 }
@@ -5,8 +5,6 @@
 //!     - reference counting
 //!     - finish parser
 //!     - tree-walking interpreter
-//!     - a proper REPL and compiler that takes CLI inputs
-//!     - parse comments
 //!     - static analysis
 //!     - types
 
@@ -18,8 +16,21 @@ use std::{
     },
     hint::black_box,
     fs::read_to_string,
+    env,
+    mem,
+    process,
 };
 use parser::Parser;
+use static_analysis::{
+    type_check::type_check,
+    fold::fold_constants,
+    unused::unused_variables,
+};
+use error::dedup_errors;
+use test_lang_vm::{
+    ModuleId,
+    Program,
+};
 pub use test_lang_common::{
     error,
     Span,
@@ -30,8 +41,39 @@ mod lexer;
 mod ast;
 mod parser;
 mod static_analysis;
+mod compiler;
+mod repl;
+
+const USAGE: &str = "Usage:
+    test_lang run <file>    Run a script
+    test_lang --repl        Start an interactive prompt
+    test_lang               Run the parser tests and benchmark";
+
 
 fn main() {
+    let args = env::args()
+        .skip(1)
+        .collect::<Vec<_>>();
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        // without arguments, run the parser tests and benchmark below
+        []=>{},
+        ["--repl"]=>{
+            repl::run();
+            return;
+        },
+        ["run", path]=>{
+            if !run_file(path) {
+                process::exit(1);
+            }
+            return;
+        },
+        _=>{
+            eprintln!("{USAGE}");
+            process::exit(1);
+        },
+    }
+
     test_expr_parser();
 
     // Test the `parse_example` file, and report errors. If this succeeds, then we can benchmark
@@ -52,9 +94,15 @@ fn main() {
     // }
     // println!();
     match res {
-        Ok(_stmts)=>{
-            let error = parser.non_fatal_errors.len() > 0;
-            for err in parser.non_fatal_errors.drain(..) {
+        Ok(stmts)=>{
+            let type_errors = type_check(&stmts, &parser.lexer.extras);
+            parser.non_fatal_errors.extend(type_errors);
+            parser.non_fatal_errors.extend(unused_variables(&stmts, &parser.lexer.extras));
+
+            let error = parser.non_fatal_errors
+                .iter()
+                .any(|err|!err.err_type().is_warning());
+            for err in dedup_errors(parser.non_fatal_errors.drain(..).collect()) {
                 err.print(&data);
             }
             if error {
@@ -80,6 +128,58 @@ fn main() {
     }
 }
 
+/// Parse, check, compile, and run the script at `path`. Returns false if anything failed.
+fn run_file(path: &str)->bool {
+    let source = match read_to_string(path) {
+        Ok(source)=>source,
+        Err(e)=>{
+            eprintln!("Could not read `{path}`: {e}");
+            return false;
+        },
+    };
+
+    let (mut parser, _) = Parser::new(&source);
+    let mut stmts = match parser.parse_file() {
+        Ok(stmts)=>stmts,
+        Err(e)=>{
+            e.print_file(&source, path);
+            return false;
+        },
+    };
+
+    let mut errors = mem::take(&mut parser.non_fatal_errors);
+    errors.extend(type_check(&stmts, &parser.lexer.extras));
+    errors.extend(unused_variables(&stmts, &parser.lexer.extras));
+
+    let failed = errors
+        .iter()
+        .any(|err|!err.err_type().is_warning());
+    for err in dedup_errors(errors) {
+        err.print_file(&source, path);
+    }
+    if failed {
+        return false;
+    }
+
+    fold_constants(&mut stmts);
+
+    let module = match compiler::compile(&stmts, ModuleId(0), path) {
+        Ok(module)=>module,
+        Err(e)=>{
+            e.print_file(&source, path);
+            return false;
+        },
+    };
+
+    let mut program = Program::new(vec![module], ModuleId(0));
+    if let Err(e) = program.run() {
+        e.print_file(&source, path);
+        return false;
+    }
+
+    return true;
+}
+
 fn test_expr_parser() {
     let source = read_to_string("expr_test").unwrap();
 
@@ -17,7 +17,6 @@ pub enum ExprItem {
     Expr(Expr),
     Integer(Span, i64),
     Float(Span, f64),
-    String(Span, String),
     Ident(Span, Symbol),
 }
 impl ExprItem {
@@ -26,7 +25,6 @@ impl ExprItem {
             Self::Expr(e)=>e,
             Self::Integer(sp, i)=>Expr::Integer(sp, i),
             Self::Float(sp, f)=>Expr::Float(sp, f),
-            Self::String(sp, s)=>Expr::String(sp, s),
             Self::Ident(sp, i)=>Expr::Named(sp, i),
         }
     }
@@ -57,6 +55,13 @@ pub enum Operator {
     LogicAnd,
     LogicOr,
 
+    // bitwise
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+
     // equality
     Equal,
     NotEqual,
@@ -64,10 +69,12 @@ pub enum Operator {
     Less,
     GreaterEqual,
     LessEqual,
+    In,
 
     // unary
     Negate,
     Not,
+    BitNot,
 
     // misc
     Index,
@@ -88,12 +95,18 @@ impl Operator {
             Mod=>BinaryOp::Mod,
             LogicAnd=>BinaryOp::LogicAnd,
             LogicOr=>BinaryOp::LogicOr,
+            BitAnd=>BinaryOp::BitAnd,
+            BitOr=>BinaryOp::BitOr,
+            BitXor=>BinaryOp::BitXor,
+            Shl=>BinaryOp::Shl,
+            Shr=>BinaryOp::Shr,
             Equal=>BinaryOp::Equal,
             NotEqual=>BinaryOp::NotEqual,
             Greater=>BinaryOp::Greater,
             Less=>BinaryOp::Less,
             GreaterEqual=>BinaryOp::GreaterEqual,
             LessEqual=>BinaryOp::LessEqual,
+            In=>BinaryOp::In,
             _=>panic!("Not allowed"),
         }
     }
@@ -103,6 +116,7 @@ impl Operator {
         match self {
             Negate=>UnaryOp::Negate,
             Not=>UnaryOp::Not,
+            BitNot=>UnaryOp::BitNot,
             _=>panic!("Not allowed"),
         }
     }
@@ -117,15 +131,22 @@ impl Operator {
                 Mod|
                 LogicAnd|
                 LogicOr|
+                BitAnd|
+                BitOr|
+                BitXor|
+                Shl|
+                Shr|
                 Equal|
                 NotEqual|
                 Greater|
                 Less|
                 GreaterEqual|
-                LessEqual=>OpType::Infix,
+                LessEqual|
+                In=>OpType::Infix,
 
             Negate|
-                Not=>OpType::Prefix,
+                Not|
+                BitNot=>OpType::Prefix,
 
             Index|
                 IndexEnd|
@@ -147,6 +168,11 @@ impl Operator {
                 Mod|
                 LogicAnd|
                 LogicOr|
+                BitAnd|
+                BitOr|
+                BitXor|
+                Shl|
+                Shr|
                 Index|
                 IndexEnd|
                 Field|
@@ -155,14 +181,16 @@ impl Operator {
                 Comma=>Left,
 
             Negate|
-                Not=>Right,
+                Not|
+                BitNot=>Right,
 
             Equal|
                 NotEqual|
                 Greater|
                 Less|
                 GreaterEqual|
-                LessEqual=>Paren,
+                LessEqual|
+                In=>Paren,
         }
     }
 
@@ -183,21 +211,32 @@ impl Operator {
                 Greater|
                 Less|
                 GreaterEqual|
-                LessEqual=>6,
+                LessEqual|
+                In=>6,
+
+            BitOr=>8,
+
+            BitXor=>10,
+
+            BitAnd=>12,
+
+            Shl|
+                Shr=>14,
 
             Add|
-                Sub=>8,
+                Sub=>16,
 
             Mul|
                 Div|
-                Mod=>10,
+                Mod=>18,
 
             Negate|
-                Not=>12,
+                Not|
+                BitNot=>20,
 
             Index|
                 Field|
-                Call=>14,
+                Call=>22,
         }
     }
 
@@ -248,10 +287,12 @@ impl<'a, 'p> ExprParser<'a, 'p> {
                 Token::Float(..)|
                 Token::Ident(..)=>self.parse_literal()?,
             Token::Sub|
-                Token::Not=>{
+                Token::Not|
+                Token::BitNot=>{
                     let op = match self.next()? {
                         Token::Sub=>Operator::Negate,
                         Token::Not=>Operator::Not,
+                        Token::BitNot=>Operator::BitNot,
                         _=>unreachable!(),
                     };
                     let start = self.span().start;
@@ -271,7 +312,7 @@ impl<'a, 'p> ExprParser<'a, 'p> {
                 self.try_next(Token::ParenEnd)?;
                 l
             },
-            _=>return Err(Error::token(self.span())),
+            _=>return Err(self.token_err(self.span())),
         };
 
         loop {
@@ -341,7 +382,7 @@ impl<'a, 'p> ExprParser<'a, 'p> {
                                 match self.next()? {
                                     Token::Comma=>{},
                                     Token::ParenEnd=>break,
-                                    _=>return Err(Error::token(self.span())),
+                                    _=>return Err(self.token_err(self.span())),
                                 }
                             }
 
@@ -388,6 +429,12 @@ impl<'a, 'p> ExprParser<'a, 'p> {
             Ok(Token::LessEqual)=>Some(Operator::LessEqual),
             Ok(Token::Keyword(Keyword::And))=>Some(Operator::LogicAnd),
             Ok(Token::Keyword(Keyword::Or))=>Some(Operator::LogicOr),
+            Ok(Token::Keyword(Keyword::In))=>Some(Operator::In),
+            Ok(Token::BitAnd)=>Some(Operator::BitAnd),
+            Ok(Token::BitOr)=>Some(Operator::BitOr),
+            Ok(Token::BitXor)=>Some(Operator::BitXor),
+            Ok(Token::Shl)=>Some(Operator::Shl),
+            Ok(Token::Shr)=>Some(Operator::Shr),
             Ok(Token::SquareStart)=>Some(Operator::Index),
             Ok(Token::Dot)=>Some(Operator::Field),
             Ok(Token::ParenStart)=>Some(Operator::Call),
@@ -403,9 +450,12 @@ impl<'a, 'p> ExprParser<'a, 'p> {
         match self.next()? {
             Token::Integer(i)=>Ok(ExprItem::Integer(self.span(), i)),
             Token::Float(f)=>Ok(ExprItem::Float(self.span(), f)),
-            Token::String(s)=>Ok(ExprItem::String(self.span(), s)),
+            Token::String(segments)=>{
+                let span = self.span();
+                self.parent.string_expr(span, segments).map(ExprItem::Expr)
+            },
             Token::Ident(i)=>Ok(ExprItem::Ident(self.span(), i)),
-            _=>Err(Error::token(self.span())),
+            _=>Err(self.token_err(self.span())),
         }
     }
 }
@@ -3,7 +3,11 @@ use logos::{
     SpannedIter,
     Span,
 };
-use string_interner::DefaultSymbol as Symbol;
+use string_interner::{
+    DefaultSymbol as Symbol,
+    StringInterner,
+};
+use std::mem;
 use crate::{
     error::*,
     lexer::*,
@@ -15,8 +19,13 @@ use crate::{
 pub mod expr;
 
 
+/// How many tokens of lookahead the parser keeps. See [`Parser::peek_n`].
+pub const LOOKAHEAD: usize = 3;
+
+
 /// A parser using techniques inspired by left-corner parsers, we have a (hopefully) linear-time
-/// parser. It is all hand-written, never backtracks, and uses 2 tokens of lookahead.
+/// parser. It is all hand-written, never backtracks, and uses up to [`LOOKAHEAD`] tokens of
+/// lookahead.
 ///
 /// Using [`crate::benchmark_parser`] I was able to get ~170MB/s for all input sizes above 300
 /// lines up to 3000 lines on my 1165G7 Framework 13. I think this is plenty sufficient for regular
@@ -26,50 +35,68 @@ pub mod expr;
 pub struct Parser<'a> {
     pub lexer: SpannedIter<'a, Token>,
     pub non_fatal_errors: Vec<Error>,
-    lookahead: [Option<Result<Token, ()>>;2],
-    spans: [Span;3],
+    lookahead: [Option<Result<Token, ()>>;LOOKAHEAD],
+    /// the span of the current token followed by the spans of the lookahead tokens
+    spans: [Span;LOOKAHEAD + 1],
     func_count: usize,
     class_count: usize,
     constructor_sym: Symbol,
+    wildcard_sym: Symbol,
 }
 impl<'a> Parser<'a> {
     /// Create a new parser from a source string
     pub fn new(source: &'a str)->(Self, Symbol) {
-        let mut lexer = Token::lexer(source).spanned();
+        Self::with_interner(source, StringInterner::new())
+    }
+
+    /// Create a new parser that keeps interning names into `interner`, so symbols from an earlier
+    /// parse stay the same. Take it back out of `lexer.extras` when finished.
+    pub fn with_interner(source: &'a str, interner: StringInterner)->(Self, Symbol) {
+        Self::with_interner_at(source, 0, interner)
+    }
+
+    /// Like [`Parser::with_interner`], but start reading at the byte offset `start`. Spans are
+    /// still relative to the start of `source`.
+    fn with_interner_at(source: &'a str, start: usize, interner: StringInterner)->(Self, Symbol) {
+        let mut lexer = Token::lexer_with_extras(source, interner).spanned();
+        lexer.bump(start);
 
         let this_sym = lexer.extras.get_or_intern("this");
 
         let mut ret = Parser {
             constructor_sym: lexer.extras.get_or_intern("constructor"),
+            wildcard_sym: lexer.extras.get_or_intern("_"),
             lexer,
-            lookahead: [None, None],
-            spans: [0..0, 0..0, 0..0],
+            lookahead: std::array::from_fn(|_|None),
+            spans: std::array::from_fn(|_|0..0),
             func_count: 0,
             class_count: 0,
             non_fatal_errors: Vec::new(),
         };
 
         // fill the lookahead buffer
-        ret.next().ok();
-        ret.next().ok();
+        for _ in 0..LOOKAHEAD {
+            ret.next().ok();
+        }
 
         return (ret, this_sym);
     }
 
     /// a helper function to peek at the next token
     fn peek(&self)->Result<&Token, Error> {
-        match &self.lookahead[0] {
-            Some(Ok(t))=>Ok(&t),
-            Some(Err(_))=>Err(Error::token(self.span())),
-            None=>Err(Error::eof(self.spans[0].clone())),
-        }
+        self.peek_n(0)
     }
 
     /// a helper function to peek at the token after the next token
     fn peek1(&self)->Result<&Token, Error> {
-        match &self.lookahead[1] {
+        self.peek_n(1)
+    }
+
+    /// peek `n` tokens past the next token. `n` must be less than [`LOOKAHEAD`].
+    fn peek_n(&self, n: usize)->Result<&Token, Error> {
+        match &self.lookahead[n] {
             Some(Ok(t))=>Ok(&t),
-            Some(Err(_))=>Err(Error::token(self.span())),
+            Some(Err(_))=>Err(self.lex_err(self.peek_n_span(n))),
             None=>Err(Error::eof(self.spans[0].clone())),
         }
     }
@@ -86,34 +113,36 @@ impl<'a> Parser<'a> {
 
     /// helper function to get the span of the token after the next token
     fn peek1_span(&self)->Span {
-        self.spans[2].clone()
+        self.peek_n_span(1)
+    }
+
+    /// helper function to get the span of the token `n` tokens past the next token
+    fn peek_n_span(&self, n: usize)->Span {
+        self.spans[n + 1].clone()
     }
 
     /// shuffles lookahead and returns the next token
     fn next(&mut self)->Result<Token, Error> {
-        // shuffle the two lookahead items
+        // shuffle the lookahead items and spans, then read a new one into the end
         let ret = self.lookahead[0].take();
-        let tmp = self.lookahead[1].take();
-        self.lookahead[0] = tmp;
-        self.lookahead[1] = self.lexer
-            .next()
-            .map(|(t,_)|t);
-
-        // shuffle the current and two lookahead spans
-        let span0 = self.spans[1].clone();
-        let span1 = self.spans[2].clone();
-        self.spans[0] = span0;
-        self.spans[1] = span1;
-        if self.lookahead[1].is_some() {
-            self.spans[2] = self.lexer.span();
-        } else {
-            let end = self.lexer.source().len();
-            self.spans[2] = end..end;
+        self.lookahead.rotate_left(1);
+        self.spans.rotate_left(1);
+
+        match self.lexer.next() {
+            Some((token, span))=>{
+                self.lookahead[LOOKAHEAD - 1] = Some(token);
+                self.spans[LOOKAHEAD] = span;
+            },
+            None=>{
+                let end = self.lexer.source().len();
+                self.lookahead[LOOKAHEAD - 1] = None;
+                self.spans[LOOKAHEAD] = end..end;
+            },
         }
 
         match ret {
             Some(Ok(t))=>Ok(t),
-            Some(Err(_))=>Err(Error::token(self.span())),
+            Some(Err(_))=>Err(self.lex_err(self.span())),
             None=>Err(Error::eof(self.spans[0].clone())),
         }
     }
@@ -148,6 +177,48 @@ impl<'a> Parser<'a> {
         self.lookahead[0].is_none()
     }
 
+    /// create an error for a token the lexer failed on. Unclosed comments, unclosed strings, and
+    /// invalid number literals get their own error types.
+    fn lex_err(&self, span: Span)->Error {
+        let text = self.lexer.source()[span.clone()].trim();
+
+        // the lexer only fails on a block comment if it is unterminated
+        if text.starts_with("/*") {
+            return Error::new(span, ErrorType::UnclosedComment);
+        }
+
+        // the lexer only fails on a string if it is unterminated. A triple quoted string can span
+        // many lines, so only the opening quotes are shown.
+        if text.starts_with("\"\"\"") {
+            return Error::new(span.start..span.start + 3, ErrorType::UnclosedString);
+        }
+        if text.starts_with('"') {
+            return Error::new(span, ErrorType::UnclosedString);
+        }
+
+        // number literals fail to lex if they overflow or are malformed
+        let number_start = text.strip_prefix('.').unwrap_or(text);
+        if number_start.starts_with(|c: char|c.is_ascii_digit()) {
+            return Error::new(span, ErrorType::InvalidNumberLiteral);
+        }
+
+        return self.token_err(span);
+    }
+
+    /// create an `UnexpectedToken` error showing the source text of the token at `span`
+    fn token_err(&self, span: Span)->Error {
+        let text = self.lexer.source()[span.clone()].trim();
+
+        // newline tokens are only whitespace, so we have to name them
+        let found = if text.is_empty() && !span.is_empty() {
+            "newline".to_string()
+        } else {
+            text.to_string()
+        };
+
+        return Error::token(span, found);
+    }
+
     fn push_err(&mut self, err: Error) {
         self.non_fatal_errors.push(err);
     }
@@ -192,7 +263,7 @@ impl<'a> Parser<'a> {
                     need_ending = false;
                     self.parse_class_stmt(publicity)
                 },
-                _=>Err(Error::token(self.peek_span())),
+                _=>Err(self.token_err(self.peek_span())),
             }?;
         } else {
             ret = match self.peek()? {
@@ -204,6 +275,10 @@ impl<'a> Parser<'a> {
                     need_ending = false;
                     self.parse_class_stmt(Permissions::empty())
                 },
+                Token::Keyword(Keyword::Enum)=>{
+                    need_ending = false;
+                    self.parse_enum_stmt()
+                },
                 Token::Keyword(Keyword::If)=>{
                     need_ending = false;
                     self.parse_if_stmt()
@@ -212,6 +287,20 @@ impl<'a> Parser<'a> {
                     need_ending = false;
                     self.parse_while_stmt()
                 },
+                Token::Keyword(Keyword::For)=>{
+                    need_ending = false;
+                    self.parse_for_stmt()
+                },
+                Token::Keyword(Keyword::Match)=>{
+                    need_ending = false;
+                    self.parse_match_stmt()
+                },
+                // A curly bracket at the start of a statement is always a block. Object literals
+                // can only appear in expression position, e.g. `let o = {x: 1}`.
+                Token::CurlyStart=>{
+                    need_ending = false;
+                    self.parse_block().map(Stmt::Block)
+                },
                 Token::Keyword(Keyword::Var|Keyword::Let)=>self.parse_create_var_stmt(),
                 Token::Keyword(Keyword::Set)=>self.parse_set_var_stmt(),
                 Token::Keyword(Keyword::Const)=>self.parse_create_const_stmt(),
@@ -257,6 +346,16 @@ impl<'a> Parser<'a> {
 
                     Ok(Stmt::Print(start..end, data))
                 },
+                Token::Keyword(Keyword::Println)=>{
+                    self.next()?;
+                    let start = self.span().start;
+
+                    let data = self.parse_expr()?;
+
+                    let end = self.span().end;
+
+                    Ok(Stmt::Println(start..end, data))
+                },
                 _=>{
                     let start = self.peek_span().start;
                     let expr = self.parse_expr()?;
@@ -294,15 +393,187 @@ impl<'a> Parser<'a> {
 
         let body = self.parse_block()?;
 
+        let else_block = self.parse_loop_else()?;
+
         let end = self.span().end;
 
         return Ok(Stmt::WhileLoop {
             span: start..end,
             condition,
             body,
+            else_block,
+        });
+    }
+
+    /// warn about conditions in an if-else chain that have the same tokens as an earlier one,
+    /// because they can never be reached
+    fn check_duplicate_conditions(&mut self, conditions: &[(Expr, Block)]) {
+        let source = self.lexer.source();
+
+        // lex the conditions again with one interner, so the same names get the same symbols.
+        // Newlines are ignored like any other whitespace.
+        let mut interner = StringInterner::new();
+        let tokens = conditions
+            .iter()
+            .map(|(condition, _)|{
+                let mut lexer = Token::lexer_with_extras(&source[condition.span()], mem::take(&mut interner));
+                let tokens = lexer
+                    .by_ref()
+                    .filter(|token|token != &Ok(Token::Newline))
+                    .collect::<Vec<_>>();
+                interner = mem::take(&mut lexer.extras);
+
+                tokens
+            })
+            .collect::<Vec<_>>();
+
+        let mut warnings = Vec::new();
+        for (i, (condition, _)) in conditions.iter().enumerate().skip(1) {
+            let first = tokens[..i]
+                .iter()
+                .position(|earlier|earlier == &tokens[i])
+                .map(|first|&conditions[first].0);
+
+            if let Some(first) = first {
+                warnings.push(Error::two_location(
+                    first.span(),
+                    condition.span(),
+                    "First checked here",
+                    ErrorType::UnreachableCondition,
+                ));
+            }
+        }
+
+        for warning in warnings {
+            self.push_err(warning);
+        }
+    }
+
+    /// parse a `for x in iterable {}` loop
+    fn parse_for_stmt(&mut self)->Result<Stmt, Error> {
+        self.try_next(Token::Keyword(Keyword::For))?;
+        let start = self.span().start;
+
+        let binding = self.ident()?;
+
+        self.try_next(Token::Keyword(Keyword::In))?;
+
+        let iterable = self.parse_expr()?;
+
+        let body = self.parse_block()?;
+
+        let else_block = self.parse_loop_else()?;
+
+        let end = self.span().end;
+
+        return Ok(Stmt::ForLoop {
+            span: start..end,
+            binding,
+            iterable,
+            body,
+            else_block,
+        });
+    }
+
+    /// parse a `match value { pattern => {}, _ => {} }` statement. The `_` arm has to be last.
+    fn parse_match_stmt(&mut self)->Result<Stmt, Error> {
+        self.try_next(Token::Keyword(Keyword::Match))?;
+        let start = self.span().start;
+
+        let scrutinee = self.parse_expr()?;
+
+        self.try_next(Token::CurlyStart)?;
+        let curly_start = self.span().start;
+
+        let mut arms = Vec::new();
+        let mut default = None;
+
+        loop {
+            self.skip_newline();
+
+            match self.peek() {
+                Ok(Token::CurlyEnd)=>{
+                    self.next()?;
+                    break;
+                },
+                // a `_` arm ends the match
+                Ok(_) if default.is_some()=>{
+                    self.try_next(Token::CurlyEnd)?;
+                    break;
+                },
+                Ok(Token::Ident(name)) if *name == self.wildcard_sym=>{
+                    self.next()?;
+                    self.try_next(Token::FatArrow)?;
+                    default = Some(self.parse_block()?);
+                },
+                Ok(_)=>{
+                    let pattern = self.parse_pattern()?;
+                    self.try_next(Token::FatArrow)?;
+                    arms.push((pattern, self.parse_block()?));
+                },
+                Err(e)=>{
+                    if e.err_type() == &ErrorType::UnexpectedEOF {
+                        let span = self.peek_span();
+                        return Err(Error::new(curly_start..span.end, ErrorType::UnclosedCurly));
+                    }
+                    return Err(e);
+                },
+            }
+
+            self.skip_newline();
+
+            // the comma between arms is optional
+            if let Ok(Token::Comma) = self.peek() {
+                self.next()?;
+            }
+        }
+
+        self.check_duplicate_conditions(&arms);
+
+        let end = self.span().end;
+
+        return Ok(Stmt::Match {
+            span: start..end,
+            scrutinee,
+            arms,
+            default,
         });
     }
 
+    /// parse a match pattern: a literal, a negative number, or `Name::Value`
+    fn parse_pattern(&mut self)->Result<Expr, Error> {
+        let start = self.peek_span().start;
+
+        let negate = match self.peek() {
+            Ok(Token::Sub)=>{
+                self.next()?;
+                true
+            },
+            _=>false,
+        };
+
+        let pattern = self.parse_literal_expr()?;
+        let span = start..self.span().end;
+
+        match (negate, pattern) {
+            (false, pattern@(Expr::Integer(..)|Expr::Float(..)|Expr::String(..)|Expr::Bool(..)|Expr::AssociatedValue(..)))=>Ok(pattern),
+            (true, Expr::Integer(_, i))=>Ok(Expr::Integer(span, -i)),
+            (true, Expr::Float(_, f))=>Ok(Expr::Float(span, -f)),
+            _=>Err(Error::new(span, ErrorType::InvalidPattern)),
+        }
+    }
+
+    /// parse the optional `else` block after a loop
+    fn parse_loop_else(&mut self)->Result<Option<Block>, Error> {
+        match self.peek() {
+            Ok(Token::Keyword(Keyword::Else))=>{
+                self.next()?;
+                Ok(Some(self.parse_block()?))
+            },
+            _=>Ok(None),
+        }
+    }
+
     /// parse an if-if else-else statement
     fn parse_if_stmt(&mut self)->Result<Stmt, Error> {
         self.try_next(Token::Keyword(Keyword::If))?;
@@ -342,6 +613,8 @@ impl<'a> Parser<'a> {
             }
         }
 
+        self.check_duplicate_conditions(&conditions);
+
         let end = self.span().end;
 
         return Ok(Stmt::If {
@@ -351,6 +624,89 @@ impl<'a> Parser<'a> {
         });
     }
 
+    /// parse an enum definition like `enum Color { Red, Green = 5, Blue }`
+    fn parse_enum_stmt(&mut self)->Result<Stmt, Error> {
+        self.try_next(Token::Keyword(Keyword::Enum))?;
+        let start = self.span().start;
+
+        let name = self.ident()?;
+
+        self.try_next(Token::CurlyStart)?;
+        let curly_start = self.span().start;
+
+        let mut items: Vec<EnumItem> = Vec::new();
+
+        loop {
+            self.skip_newline();
+
+            match self.peek() {
+                Ok(Token::CurlyEnd)=>{
+                    self.next()?;
+                    break;
+                },
+                Ok(_)=>{
+                    let variant = self.ident()?;
+                    let variant_span = self.span();
+
+                    let item = match self.peek() {
+                        Ok(Token::Assign)=>{
+                            self.next()?;
+
+                            let negate = match self.peek() {
+                                Ok(Token::Sub)=>{
+                                    self.next()?;
+                                    true
+                                },
+                                _=>false,
+                            };
+
+                            let Token::Integer(value) = self.next()? else {
+                                return Err(Error::new(self.span(), ErrorType::ExpectedToken("Integer".to_string())));
+                            };
+
+                            let value = if negate {-value} else {value};
+                            EnumItem::NameValue(variant_span.start..self.span().end, variant, value)
+                        },
+                        _=>EnumItem::Name(variant_span, variant),
+                    };
+
+                    if let Some(first) = items.iter().find(|i|i.name() == variant) {
+                        return Err(Error::two_location(
+                            first.span(),
+                            item.span(),
+                            "First defined here",
+                            ErrorType::VariantRedefined,
+                        ));
+                    }
+
+                    items.push(item);
+                },
+                Err(e)=>{
+                    if e.err_type() == &ErrorType::UnexpectedEOF {
+                        let span = self.peek_span();
+                        return Err(Error::new(curly_start..span.end, ErrorType::UnclosedCurly));
+                    }
+                    return Err(e);
+                },
+            }
+
+            self.skip_newline();
+
+            // the comma between variants is optional
+            if let Ok(Token::Comma) = self.peek() {
+                self.next()?;
+            }
+        }
+
+        let end = self.span().end;
+
+        return Ok(Stmt::Enum {
+            span: start..end,
+            name,
+            items,
+        });
+    }
+
     /// parse a class definition statement
     fn parse_class_stmt(&mut self, permissions: Permissions)->Result<Stmt, Error> {
         self.try_next(Token::Keyword(Keyword::Class))?;
@@ -358,6 +714,14 @@ impl<'a> Parser<'a> {
 
         let name = self.ident()?;
 
+        let parent = match self.peek() {
+            Ok(Token::Colon)=>{
+                self.next()?;
+                Some(self.ident()?)
+            },
+            _=>None,
+        };
+
         self.try_next(Token::CurlyStart)?;
         let curly_start = self.span().start;
 
@@ -409,7 +773,7 @@ impl<'a> Parser<'a> {
 
                     methods.push(method);
                 },
-                Ok(_)=>return Err(Error::token(self.peek_span())),
+                Ok(_)=>return Err(self.token_err(self.peek_span())),
                 Err(e)=>{
                     if e.err_type() == &ErrorType::UnexpectedEOF {
                         let span = self.peek_span();
@@ -451,6 +815,7 @@ impl<'a> Parser<'a> {
             id: self.get_class_id(),
             permissions,
             name,
+            parent,
             constructor,
             fields,
             methods,
@@ -464,19 +829,39 @@ impl<'a> Parser<'a> {
         let start = self.span().start;
 
         let mut left = vec![self.ident()?];
+        // the path as an expression, used when desugaring compound assignments
+        let mut left_expr = Expr::Named(self.span(), left[0]);
+        let left_start = self.span().start;
 
         while let Ok(Token::Dot) = self.peek() {
             self.next()?;
 
-            left.push(self.ident()?);
+            let field = self.ident()?;
+            let end = self.span().end;
+            left.push(field);
+            left_expr = Expr::Field(left_start..end, Box::new(left_expr), field);
         }
 
-        self.try_next(Token::Assign)?;
+        // `set x += 1` is the same as `set x = x + 1`
+        let op = match self.next()? {
+            Token::Assign=>None,
+            Token::AddAssign=>Some(BinaryOp::Add),
+            Token::SubAssign=>Some(BinaryOp::Sub),
+            Token::MulAssign=>Some(BinaryOp::Mul),
+            Token::DivAssign=>Some(BinaryOp::Div),
+            Token::ModAssign=>Some(BinaryOp::Mod),
+            _=>return Err(Error::new(self.span(), ErrorType::ExpectedToken("=".to_string()))),
+        };
 
-        let data = self.parse_expr()?;
+        let right = self.parse_expr()?;
 
         let end = self.span().end;
 
+        let data = match op {
+            Some(op)=>Expr::BinaryOp(left_start..end, op, Box::new([left_expr, right])),
+            None=>right,
+        };
+
         return Ok(Stmt::SetVar {
             span: start..end,
             left,
@@ -490,8 +875,18 @@ impl<'a> Parser<'a> {
         let start = self.span().start;
 
         let name = self.ident()?;
+        let type_annotation = self.parse_type_annotation()?;
 
-        self.try_next(Token::Assign)?;
+        // unlike `let` and `var`, constants can't be initialized later
+        match self.peek() {
+            Ok(Token::Assign)=>{
+                self.next()?;
+            },
+            _=>{
+                let end = self.span().end;
+                return Err(Error::new(start..end, ErrorType::ConstWithoutValue));
+            },
+        }
 
         let data = self.parse_expr()?;
 
@@ -500,6 +895,7 @@ impl<'a> Parser<'a> {
         return Ok(Stmt::CreateConst {
             span: start..end,
             name,
+            type_annotation,
             data,
         });
     }
@@ -528,7 +924,7 @@ impl<'a> Parser<'a> {
                                 }
                             },
                             Token::Keyword(Keyword::Mut)=>perms |= Permissions::PUBLIC_MUTABLE,
-                            _=>return Err(Error::token(self.span())),
+                            _=>return Err(self.token_err(self.span())),
                         }
 
                         self.try_next(Token::ParenEnd)?;
@@ -553,7 +949,7 @@ impl<'a> Parser<'a> {
         match self.next() {
             Ok(Token::Keyword(Keyword::Var))=>var_type |= Permissions::REASSIGN,
             Ok(Token::Keyword(Keyword::Let))=>var_type |= Permissions::IS_VARIABLE,
-            _=>return Err(Error::token(self.span())),
+            _=>return Err(self.token_err(self.span())),
         }
 
         match self.peek() {
@@ -567,12 +963,14 @@ impl<'a> Parser<'a> {
         return Ok(var_type);
     }
 
-    /// parses a var creation statement
+    /// parses a var creation statement. The initializer is optional for both `let` and `var`;
+    /// the type checker reports reading the variable before it is assigned as `VarUninitialized`.
     fn parse_create_var_stmt(&mut self)->Result<Stmt, Error> {
         let start = self.peek_span().start;
         let var_type = self.parse_var_type()?;
 
         let name = self.ident()?;
+        let type_annotation = self.parse_type_annotation()?;
 
         let data = match self.peek() {
             Ok(Token::Assign)=>{
@@ -588,6 +986,7 @@ impl<'a> Parser<'a> {
             span: start..end,
             var_type,
             name,
+            type_annotation,
             data,
         });
     }
@@ -631,11 +1030,9 @@ impl<'a> Parser<'a> {
         };
         let start = self.span().start;
 
-        let params = self.parse_paren_list(Self::parse_function_param)?;
+        let (params, variadic) = self.parse_function_params()?;
 
-        if params.len() > u8::MAX as usize {
-            self.push_err(Error::new(self.span(), ErrorType::TooManyParams));
-        }
+        let return_type = self.parse_type_annotation()?;
 
         let body = self.parse_block()?;
 
@@ -648,6 +1045,8 @@ impl<'a> Parser<'a> {
             span: start..end,
             name,
             params,
+            variadic,
+            return_type,
             body,
         });
     }
@@ -675,14 +1074,128 @@ impl<'a> Parser<'a> {
     }
 
     /// Parse a single function parameter
-    fn parse_function_param(&mut self)->Result<(Span, Permissions, Symbol), Error> {
+    /// Parse the parameter list of a function. Returns the parameters and whether the last one is a
+    /// `...rest` parameter.
+    fn parse_function_params(&mut self)->Result<(Vec<(Span, Permissions, Symbol, Option<TypeExpr>)>, bool), Error> {
+        let params = self.parse_paren_list(|this|{
+            let start = this.peek_span().start;
+            let rest = this.peek()? == &Token::Ellipsis;
+            if rest {
+                this.next()?;
+            }
+
+            // the span of a rest parameter includes the `...`
+            let mut param = this.parse_function_param()?;
+            param.0.start = start;
+
+            Ok((param, rest))
+        })?;
+
+        if params.len() > u8::MAX as usize {
+            self.push_err(Error::new(self.span(), ErrorType::TooManyParams));
+        }
+
+        // only the last parameter can be a rest parameter
+        let variadic = params.last().is_some_and(|(_, rest)|*rest);
+        for ((span, ..), _) in params.iter().rev().skip(1).filter(|(_, rest)|*rest) {
+            self.push_err(Error::new(span.clone(), ErrorType::RestParamNotLast));
+        }
+
+        let params = params
+            .into_iter()
+            .map(|(param, _)|param)
+            .collect();
+
+        return Ok((params, variadic));
+    }
+
+    fn parse_function_param(&mut self)->Result<(Span, Permissions, Symbol, Option<TypeExpr>), Error> {
         let start = self.peek_span().start;
         let var_type = self.parse_partial_var_type()?;
 
         let name = self.ident()?;
+        let type_expr = self.parse_type_annotation()?;
         let end = self.span().end;
 
-        return Ok((start..end, var_type, name));
+        return Ok((start..end, var_type, name, type_expr));
+    }
+
+    /// Parse an optional `: Type` annotation
+    fn parse_type_annotation(&mut self)->Result<Option<TypeExpr>, Error> {
+        match self.peek() {
+            Ok(Token::Colon)=>{
+                self.next()?;
+                Ok(Some(self.parse_type()?))
+            },
+            _=>Ok(None),
+        }
+    }
+
+    /// Parse a type name with optional type arguments in angle brackets
+    fn parse_type(&mut self)->Result<TypeExpr, Error> {
+        let (ty, closed_outer) = self.parse_nested_type()?;
+        if closed_outer {
+            return Err(self.token_err(self.span()));
+        }
+
+        return Ok(ty);
+    }
+
+    /// Parse a type that may be inside of another type's arguments. The lexer reads the end of
+    /// `List<List<Int>>` as one `>>` token, so the `bool` is true if that token also closed the
+    /// arguments this type is in.
+    fn parse_nested_type(&mut self)->Result<(TypeExpr, bool), Error> {
+        let name = self.ident()?;
+        let start = self.span().start;
+
+        match self.peek() {
+            Ok(Token::Less)=>{
+                self.next()?;
+                let less_start = self.span().start;
+
+                let mut args = Vec::new();
+                let mut closed_outer = false;
+                loop {
+                    let (arg, closed) = match self.parse_nested_type() {
+                        Ok(t)=>t,
+                        Err(e)=>{
+                            if e.err_type() == &ErrorType::UnexpectedEOF {
+                                let span = self.peek_span();
+                                return Err(Error::new(less_start..span.end, ErrorType::ExpectedToken(">".to_string())));
+                            }
+                            return Err(e);
+                        },
+                    };
+                    args.push(arg);
+                    if closed {
+                        break;
+                    }
+
+                    match self.next() {
+                        Ok(Token::Greater)=>break,
+                        Ok(Token::Shr)=>{
+                            closed_outer = true;
+                            break;
+                        },
+                        Ok(Token::Comma)=>{},
+                        Ok(_)=>return Err(Error::new(self.span(), ErrorType::ExpectedToken(">".to_string()))),
+                        Err(e)=>{
+                            if e.err_type() == &ErrorType::UnexpectedEOF {
+                                let span = self.peek_span();
+                                return Err(Error::new(less_start..span.end, ErrorType::ExpectedToken(">".to_string())));
+                            }
+                            return Err(e);
+                        },
+                    }
+                }
+
+                // the second `>` of a `>>` belongs to the outer type
+                let end = self.span().end - closed_outer as usize;
+
+                Ok((TypeExpr::Generic(start..end, name, args), closed_outer))
+            },
+            _=>Ok((TypeExpr::Named(self.span(), name), false)),
+        }
     }
 
     /// parse a block of statements in curly brackets
@@ -737,6 +1250,46 @@ impl<'a> Parser<'a> {
 
     /// Parse a single expression
     pub fn parse_expr(&mut self)->Result<Expr, Error> {
+        let condition = self.parse_simple_expr()?;
+
+        // `start..end` and `start..=end`
+        let inclusive = match self.peek() {
+            Ok(Token::DotDot)=>Some(false),
+            Ok(Token::DotDotEq)=>Some(true),
+            _=>None,
+        };
+        if let Some(inclusive) = inclusive {
+            self.next()?;
+            let end = self.parse_simple_expr()?;
+            let span = condition.span().start..self.span().end;
+
+            return Ok(Expr::Range(span, Box::new([condition, end]), inclusive));
+        }
+
+        // `condition ? then : otherwise`. Like binary operations, nested conditionals need
+        // parenthesis.
+        let Ok(Token::Question) = self.peek() else {
+            return Ok(condition);
+        };
+        self.next()?;
+
+        let then = self.parse_simple_expr()?;
+        self.try_next(Token::Colon)?;
+        let otherwise = self.parse_simple_expr()?;
+
+        let start = condition.span().start;
+        let end = self.span().end;
+
+        return Ok(Expr::Conditional(start..end, Box::new([condition, then, otherwise])));
+    }
+
+    /// Parse an expression that is not a conditional or range
+    fn parse_simple_expr(&mut self)->Result<Expr, Error> {
+        self.parse_bin_op_expr(0)
+    }
+
+    /// Parse an operand of a binary operation
+    fn parse_operand_expr(&mut self)->Result<Expr, Error> {
         let left = match self.peek()? {
             Token::Keyword(Keyword::Copy)=>{
                 self.next()?;
@@ -755,8 +1308,8 @@ impl<'a> Parser<'a> {
 
                 Expr::Ref(start..end, var_type, name)
             },
-            Token::Not|Token::Sub=>self.parse_unary_op_expr()?,
-            _=>self.parse_bin_op_expr()?,
+            Token::Not|Token::Sub|Token::BitNot=>self.parse_unary_op_expr()?,
+            _=>return self.parse_paren_expr(),
         };
 
         return self.parse_tail_expr(left);
@@ -769,7 +1322,8 @@ impl<'a> Parser<'a> {
                 // Index
                 Ok(Token::SquareStart)=>{
                     self.next()?;
-                    let start = self.span().start;
+                    let square_start = self.span().start;
+                    let start = left.span().start;
 
                     self.skip_newline();
 
@@ -778,7 +1332,7 @@ impl<'a> Parser<'a> {
                         Err(e)=>{
                             if e.err_type() == &ErrorType::UnexpectedEOF {
                                 let span = self.peek_span();
-                                return Err(Error::new(start..span.end, ErrorType::UnclosedSquare));
+                                return Err(Error::new(square_start..span.end, ErrorType::UnclosedSquare));
                             }
                             return Err(e);
                         },
@@ -788,11 +1342,11 @@ impl<'a> Parser<'a> {
 
                     match self.next() {
                         Ok(Token::SquareEnd)=>{},
-                        Ok(_)=>return Err(Error::token(self.span())),
+                        Ok(_)=>return Err(self.token_err(self.span())),
                         Err(e)=>{
                             if e.err_type() == &ErrorType::UnexpectedEOF {
                                 let span = self.peek_span();
-                                return Err(Error::new(start..span.end, ErrorType::UnclosedSquare));
+                                return Err(Error::new(square_start..span.end, ErrorType::UnclosedSquare));
                             }
                             return Err(e);
                         },
@@ -805,7 +1359,7 @@ impl<'a> Parser<'a> {
                 // Field
                 Ok(Token::Dot)=>{
                     self.next()?;
-                    let start = self.span().start;
+                    let start = left.span().start;
                     let name = self.ident()?;
                     let end = self.span().end;
 
@@ -813,8 +1367,8 @@ impl<'a> Parser<'a> {
                 },
                 // Function call
                 Ok(Token::ParenStart)=>{
-                    let start = self.peek_span().start;
-                    let mut items = self.parse_paren_list(Self::parse_expr)?;
+                    let start = left.span().start;
+                    let mut items = self.parse_paren_list(Self::parse_call_arg)?;
 
                     if items.len() > u8::MAX as usize {
                         self.push_err(Error::new(self.span(), ErrorType::TooManyArgs));
@@ -852,6 +1406,22 @@ impl<'a> Parser<'a> {
         return Ok(left);
     }
 
+    /// parse a function call argument, which may be a `...list` spread
+    fn parse_call_arg(&mut self)->Result<Expr, Error> {
+        match self.peek()? {
+            Token::Ellipsis=>{
+                self.next()?;
+                let start = self.span().start;
+
+                let inner = self.parse_expr()?;
+                let end = self.span().end;
+
+                Ok(Expr::Spread(start..end, Box::new(inner)))
+            },
+            _=>self.parse_expr(),
+        }
+    }
+
     /// a generic function to parse a comma separated list of `T` which is parsed by the function
     /// `F`
     fn parse_paren_list<T, F:FnMut(&mut Self)->Result<T, Error>>(&mut self, mut f: F)->Result<Vec<T>, Error> {
@@ -919,12 +1489,12 @@ impl<'a> Parser<'a> {
         return Ok(items);
     }
 
-    /// parse a binary operation, if possible
-    fn parse_bin_op(&mut self, peek_second: bool)->Option<BinaryOp> {
-        let peek = if peek_second {
-            self.peek1()
-        } else {
-            self.peek()
+    /// peek at the next binary operator. Returns the operator and whether it is on the next line.
+    fn peek_bin_op(&self)->Option<(BinaryOp, bool)> {
+        // an operator at the start of the next line continues the expression
+        let (peek, newline) = match self.peek() {
+            Ok(Token::Newline)=>(self.peek1(), true),
+            peek=>(peek, false),
         };
         let op = match peek {
             Ok(Token::Add)=>BinaryOp::Add,
@@ -940,41 +1510,84 @@ impl<'a> Parser<'a> {
             Ok(Token::LessEqual)=>BinaryOp::LessEqual,
             Ok(Token::Keyword(Keyword::And))=>BinaryOp::LogicAnd,
             Ok(Token::Keyword(Keyword::Or))=>BinaryOp::LogicOr,
+            Ok(Token::Keyword(Keyword::In))=>BinaryOp::In,
+            Ok(Token::BitAnd)=>BinaryOp::BitAnd,
+            Ok(Token::BitOr)=>BinaryOp::BitOr,
+            Ok(Token::BitXor)=>BinaryOp::BitXor,
+            Ok(Token::Shl)=>BinaryOp::Shl,
+            Ok(Token::Shr)=>BinaryOp::Shr,
             _=>return None,
         };
 
-        self.next().unwrap();
-
-        return Some(op);
+        return Some((op, newline));
     }
 
-    /// parse a binary operation, if we can.
-    fn parse_bin_op_expr(&mut self)->Result<Expr, Error> {
+    /// parse a chain of binary operations with operators that bind at least as tightly as
+    /// `min_precedence`. Operators of the same precedence are left associative, except
+    /// comparisons, which don't chain.
+    fn parse_bin_op_expr(&mut self, min_precedence: u8)->Result<Expr, Error> {
         let start = self.peek_span().start;
-        // parse the left side
-        let left = self.parse_paren_expr()?;
-
-        // peek to see if we have an newline or an operator. Without this peek, we will sometimes
-        // remove newlines used by `parse_stmt`
-        let op = match self.peek()? {
-            Token::Newline=>match self.parse_bin_op(true) {
-                Some(op)=>op,
-                // if we have no operator, then return the left side expression
-                _=>return Ok(left),
-            },
-            _=>match self.parse_bin_op(false) {
-                Some(op)=>op,
-                _=>return Ok(left),
-            },
-        };
+        let mut left = self.parse_operand_expr()?;
+        let mut compared = false;
+
+        // peek before consuming anything. Without this peek, we will sometimes remove newlines
+        // used by `parse_stmt`
+        while let Some((op, newline)) = self.peek_bin_op() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            // `1 < 2 == true` is most likely a mistake, so it needs parenthesis
+            if op.is_comparison() && compared {
+                let span = if newline {self.peek1_span()} else {self.peek_span()};
+                return Err(Error::new(span, ErrorType::ChainedComparison));
+            }
+            compared |= op.is_comparison();
 
-        self.skip_newline();
+            if newline {
+                self.next()?;
+            }
+            self.next()?;
+            self.skip_newline();
 
-        // parse the right expression
-        let right = self.parse_paren_expr()?;
-        let end = self.span().end;
+            // parse the right expression
+            let right = self.parse_bin_op_expr(op.precedence() + 1)?;
+            let end = self.span().end;
+
+            left = self.bin_op(start..end, op, left, right);
+        }
 
-        return Ok(Expr::BinaryOp(start..end, op, Box::new([left, right])));
+        return Ok(left);
+    }
+
+    /// create a binary operation, warning about literal operands that will fail or truncate
+    fn bin_op(&mut self, span: Span, op: BinaryOp, left: Expr, right: Expr)->Expr {
+        // `/` on integers truncates, which is surprising for something like `1 / 2`
+        if let (BinaryOp::Div, Expr::Integer(_, l), Expr::Integer(_, r)) = (op, &left, &right) {
+            if *r != 0 && l % r != 0 {
+                self.push_err(Error::new(span.clone(), ErrorType::TruncatingDivision(*l, *r)));
+            }
+        }
+
+        // we already know dividing by a literal zero will fail at runtime
+        if let (BinaryOp::Div|BinaryOp::Mod, Expr::Integer(_, 0)) = (op, &right) {
+            self.push_err(Error::new(span.clone(), ErrorType::DivideByZero));
+        }
+
+        // same for arithmetic on two literals that overflows
+        if let (Expr::Integer(_, l), Expr::Integer(_, r)) = (&left, &right) {
+            let overflows = match op {
+                BinaryOp::Add=>l.checked_add(*r).is_none(),
+                BinaryOp::Sub=>l.checked_sub(*r).is_none(),
+                BinaryOp::Mul=>l.checked_mul(*r).is_none(),
+                _=>false,
+            };
+
+            if overflows {
+                self.push_err(Error::new(span.clone(), ErrorType::IntegerOverflow));
+            }
+        }
+
+        return Expr::BinaryOp(span, op, Box::new([left, right]));
     }
 
     /// parse a unary expression
@@ -983,7 +1596,8 @@ impl<'a> Parser<'a> {
         let op = match self.next()? {
             Token::Sub=>UnaryOp::Negate,
             Token::Not=>UnaryOp::Not,
-            _=>return Err(Error::token(self.span())),
+            Token::BitNot=>UnaryOp::BitNot,
+            _=>return Err(self.token_err(self.span())),
         };
         let start = self.span().start;
 
@@ -1033,6 +1647,49 @@ impl<'a> Parser<'a> {
         return self.parse_tail_expr(left);
     }
 
+    /// turn the segments of a string token into a string, or an interpolated string if it has any
+    /// `${...}` in it
+    pub fn string_expr(&mut self, span: Span, segments: Vec<StringSegment>)->Result<Expr, Error> {
+        let segments = match <[StringSegment;1]>::try_from(segments) {
+            Ok([StringSegment::Text(s)])=>return Ok(Expr::String(span, s)),
+            Ok(segment)=>Vec::from(segment),
+            Err(segments)=>segments,
+        };
+
+        let mut parts = Vec::with_capacity(segments.len());
+        for segment in segments {
+            match segment {
+                StringSegment::Text(s)=>parts.push(StringPart::Literal(s)),
+                StringSegment::Interpolation(expr_span)=>{
+                    parts.push(StringPart::Expr(self.parse_interpolation(expr_span)?));
+                },
+            }
+        }
+
+        return Ok(Expr::StringInterp(span, parts));
+    }
+
+    /// parse the expression in a `${...}` with a new parser starting at `span`. The expression has
+    /// to fill the braces.
+    fn parse_interpolation(&mut self, span: Span)->Result<Expr, Error> {
+        let interner = mem::take(&mut self.lexer.extras);
+        let (mut parser, _) = Parser::with_interner_at(self.lexer.source(), span.start, interner);
+        parser.func_count = self.func_count;
+        parser.class_count = self.class_count;
+
+        let result = match parser.parse_expr() {
+            Ok(_) if parser.peek_span().start != span.end=>Err(parser.token_err(parser.peek_span())),
+            result=>result,
+        };
+
+        self.lexer.extras = mem::take(&mut parser.lexer.extras);
+        self.func_count = parser.func_count;
+        self.class_count = parser.class_count;
+        self.non_fatal_errors.append(&mut parser.non_fatal_errors);
+
+        return result;
+    }
+
     /// parse a literal expression
     fn parse_literal_expr(&mut self)->Result<Expr, Error> {
         let start = self.peek_span();
@@ -1049,9 +1706,25 @@ impl<'a> Parser<'a> {
             },
             Token::Integer(i)=>Ok(Expr::Integer(start, i)),
             Token::Float(f)=>Ok(Expr::Float(start, f)),
-            Token::String(s)=>Ok(Expr::String(start, s)),
+            Token::String(segments)=>self.string_expr(start, segments),
             Token::Keyword(Keyword::True)=>Ok(Expr::Bool(start, true)),
             Token::Keyword(Keyword::False)=>Ok(Expr::Bool(start, false)),
+            Token::Keyword(Keyword::Super)=>Ok(Expr::Super(start)),
+            // an anonymous function
+            Token::Keyword(Keyword::Function)=>{
+                let (params, variadic) = self.parse_function_params()?;
+
+                let return_type = self.parse_type_annotation()?;
+                let body = self.parse_block()?;
+                let end = self.span().end;
+
+                Ok(Expr::Lambda(start.start..end, Box::new(Lambda {
+                    params,
+                    variadic,
+                    return_type,
+                    body,
+                })))
+            },
             Token::CurlyStart=>{
                 let start = self.span().start;
                 let mut items = Vec::new();
@@ -1068,17 +1741,23 @@ impl<'a> Parser<'a> {
                             let name = self.ident()?;
                             let name_span = self.span();
 
-                            self.try_next(Token::Colon)?;
-
-                            let expr = match self.parse_expr() {
-                                Ok(e)=>e,
-                                Err(e)=>{
-                                    if e.err_type() == &ErrorType::UnexpectedEOF {
-                                        let span = self.peek_span();
-                                        return Err(Error::new(start..span.end, ErrorType::UnclosedCurly));
+                            let expr = match self.peek() {
+                                Ok(Token::Colon)=>{
+                                    self.next()?;
+
+                                    match self.parse_expr() {
+                                        Ok(e)=>e,
+                                        Err(e)=>{
+                                            if e.err_type() == &ErrorType::UnexpectedEOF {
+                                                let span = self.peek_span();
+                                                return Err(Error::new(start..span.end, ErrorType::UnclosedCurly));
+                                            }
+                                            return Err(e);
+                                        },
                                     }
-                                    return Err(e);
                                 },
+                                // shorthand: `{x}` is the same as `{x: x}`
+                                _=>Expr::Named(name_span.clone(), name),
                             };
 
                             items.push((name_span, name, expr));
@@ -1097,7 +1776,7 @@ impl<'a> Parser<'a> {
                     match self.next() {
                         Ok(Token::CurlyEnd)=>break,
                         Ok(Token::Comma)=>{},
-                        Ok(_)=>return Err(Error::token(self.span())),
+                        Ok(_)=>return Err(self.token_err(self.span())),
                         Err(e)=>{
                             if e.err_type() == &ErrorType::UnexpectedEOF {
                                 let span = self.peek_span();
@@ -1147,7 +1826,7 @@ impl<'a> Parser<'a> {
                     match self.next() {
                         Ok(Token::SquareEnd)=>break,
                         Ok(Token::Comma)=>{},
-                        Ok(_)=>return Err(Error::token(self.span())),
+                        Ok(_)=>return Err(self.token_err(self.span())),
                         Err(e)=>{
                             if e.err_type() == &ErrorType::UnexpectedEOF {
                                 let span = self.peek_span();
@@ -1161,7 +1840,208 @@ impl<'a> Parser<'a> {
 
                 Ok(Expr::List(start..end, items))
             },
-            _=>Err(Error::token(self.span())),
+            // the token can't start an expression
+            _=>Err(Error::new(self.span(), ErrorType::ExpectedExpression)),
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the error type of parsing `source`, which has to fail
+    fn parse_err(source: &str)->ErrorType {
+        let (mut parser, _) = Parser::new(source);
+
+        parser.parse_file().unwrap_err().err_type().clone()
+    }
+
+    /// the values of the variables created in `source`
+    fn values(source: &str)->Vec<Expr> {
+        let (mut parser, _) = Parser::new(source);
+
+        parser.parse_file()
+            .unwrap()
+            .into_iter()
+            .map(|stmt|match stmt {
+                Stmt::CreateVar{data: Some(data), ..}=>data,
+                _=>panic!("Expected a variable"),
+            })
+            .collect()
+    }
+
+    /// the error types of the warnings from parsing `source`, which has to succeed
+    fn warnings(source: &str)->Vec<ErrorType> {
+        let (mut parser, _) = Parser::new(source);
+        parser.parse_file().unwrap();
+
+        parser.non_fatal_errors
+            .iter()
+            .map(|err|err.err_type().clone())
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_conditions() {
+        let source = "if x == 1 {\n} else if x  ==  1 {\n}\n";
+        assert_eq!(warnings(source), [ErrorType::UnreachableCondition]);
+
+        let source = "match x {\n    1 => {\n    }\n    1 => {\n    }\n    _ => {\n    }\n}\n";
+        assert_eq!(warnings(source), [ErrorType::UnreachableCondition]);
+    }
+
+    #[test]
+    fn different_conditions_are_not_duplicates() {
+        let source = "if x == \"a b\" {\n} else if x == \"ab\" {\n}\n";
+        assert_eq!(warnings(source), []);
+
+        let source = "if a == b {\n} else if b == a {\n}\n";
+        assert_eq!(warnings(source), []);
+
+        let source = "match x {\n    \"a b\" => {\n    }\n    \"ab\" => {\n    }\n    _ => {\n    }\n}\n";
+        assert_eq!(warnings(source), []);
+    }
+
+    #[test]
+    fn lookahead() {
+        let (mut parser, _) = Parser::new("let x = 1");
+
+        assert_eq!(parser.peek().ok(), Some(&Token::Keyword(Keyword::Let)));
+        assert!(matches!(parser.peek1(), Ok(Token::Ident(_))));
+        assert_eq!(parser.peek_n(2).ok(), Some(&Token::Assign));
+        assert_eq!(parser.peek_n_span(2), 6..7);
+
+        parser.next().unwrap();
+        assert!(matches!(parser.peek(), Ok(Token::Ident(_))));
+        assert_eq!(parser.peek_n(2).ok(), Some(&Token::Integer(1)));
+        assert_eq!(parser.peek_n_span(2), 8..9);
+
+        // past the end of the input
+        parser.next().unwrap();
+        assert!(parser.peek_n(2).is_err());
+        assert_eq!(parser.peek_n_span(2), 9..9);
+    }
+
+    #[test]
+    fn both_parsers_agree() {
+        let source = "1 - 2 - 3 * 4";
+
+        let (mut parser, _) = Parser::new(source);
+        let main = parser.parse_expr().unwrap();
+        let (mut parser, _) = Parser::new(source);
+        let pratt = expr::ExprParser::new(&mut parser).parse().unwrap();
+
+        assert_eq!(main.to_string(), "(1-2)-(3*4)");
+        assert_eq!(main.to_string(), pratt.to_string());
+    }
+
+    #[test]
+    fn chained_comparison() {
+        assert_eq!(parse_err("let x = 1 < 2 == true\n"), ErrorType::ChainedComparison);
+        assert_eq!(parse_err("let x = 1 < 2\n    == true\n"), ErrorType::ChainedComparison);
+        assert_eq!(warnings("let x = (1 < 2) == true\nprintln x\n"), []);
+    }
+
+    #[test]
+    fn postfix_spans() {
+        let source = "let a = f()[0]\nlet b = me.items[1]\nlet c = 1 + 2";
+        let values = values(source);
+
+        let spans = values
+            .iter()
+            .map(|value|&source[value.span()])
+            .collect::<Vec<_>>();
+        assert_eq!(spans, ["f()[0]", "me.items[1]", "1 + 2"]);
+    }
+
+    #[test]
+    fn type_annotations() {
+        let source = "function f(a: Int, b: Map<String, List<Int>>, c): Bool {\n}\n";
+        let (mut parser, _) = Parser::new(source);
+        let stmts = parser.parse_file().unwrap();
+        let Some(Stmt::Function(_, func)) = stmts.first() else {
+            panic!("Expected a function");
+        };
+
+        // show each type with its source, since symbols have no names of their own
+        let show = |type_expr: &Option<TypeExpr>|type_expr
+            .as_ref()
+            .map(|type_expr|&source[type_expr.span()]);
+
+        let params = func.params
+            .iter()
+            .map(|(_, _, _, type_expr)|show(type_expr))
+            .collect::<Vec<_>>();
+        assert_eq!(params, [Some("Int"), Some("Map<String, List<Int>>"), None]);
+        assert_eq!(show(&func.return_type), Some("Bool"));
+
+        let Some(TypeExpr::Generic(_, _, args)) = &func.params[1].3 else {
+            panic!("Expected a generic type");
+        };
+        assert!(matches!(args[..], [TypeExpr::Named(..), TypeExpr::Generic(..)]));
+    }
+
+    #[test]
+    fn invalid_number_literal() {
+        assert_eq!(parse_err("let x = 99999999999999999999\n"), ErrorType::InvalidNumberLiteral);
+        assert_eq!(parse_err("let x = 1.2.3\n"), ErrorType::InvalidNumberLiteral);
+        // `0...3` is `0` followed by a spread
+        assert_eq!(parse_err("let x = 0...3\n"), ErrorType::LineEnding);
+    }
+
+    #[test]
+    fn floats_and_ranges() {
+        let floats = values("let a = 1.5\nlet b = .5e1\nlet c = 0..3\nlet d = 1..=2\nlet e = 1.5..2.5\n");
+
+        assert!(matches!(floats[0], Expr::Float(_, n) if n == 1.5));
+        assert!(matches!(floats[1], Expr::Float(_, n) if n == 5.0));
+        assert!(matches!(&floats[2], Expr::Range(_, ends, false) if matches!(ends[0], Expr::Integer(_, 0))));
+        assert!(matches!(&floats[3], Expr::Range(_, ends, true) if matches!(ends[1], Expr::Integer(_, 2))));
+        assert!(matches!(&floats[4], Expr::Range(_, ends, false) if matches!(ends[0], Expr::Float(_, n) if n == 1.5)));
+
+        // a number followed by a `.` and a name is a method call
+        let calls = values("let a = 5.abs()\nlet b = 2.5.floor()\n");
+        assert!(matches!(&calls[0], Expr::MethodCall(_, _, items) if matches!(items[0], Expr::Integer(_, 5))));
+        assert!(matches!(&calls[1], Expr::MethodCall(_, _, items) if matches!(items[0], Expr::Float(..))));
+    }
+
+    #[test]
+    fn shorthand_object_fields() {
+        let values = values("let p = {x, y: 2}\n");
+        let Expr::Object(_, fields) = &values[0] else {
+            panic!("Expected an object");
+        };
+
+        assert!(matches!(&fields[0], (_, name, Expr::Named(_, value)) if name == value));
+        assert!(matches!(fields[1].2, Expr::Integer(_, 2)));
+    }
+
+    #[test]
+    fn unclosed_strings() {
+        assert_eq!(parse_err("let x = \"abc\n"), ErrorType::UnclosedString);
+        assert_eq!(parse_err("let x = \"\"\"\nabc\n"), ErrorType::UnclosedString);
+    }
+
+    #[test]
+    fn unexpected_string_is_not_unclosed() {
+        assert_eq!(parse_err("let x = [1 \"a\"]\n"), ErrorType::UnexpectedToken("\"a\"".to_string()));
+    }
+
+    #[test]
+    fn unexpected_number_is_not_invalid() {
+        assert_eq!(parse_err("let x = [1 2]\n"), ErrorType::UnexpectedToken("2".to_string()));
+    }
+
+    #[test]
+    fn declarations_without_values() {
+        for source in ["let x\n", "var x\n", "var mut x\n", "let x: Int\n"] {
+            let (mut parser, _) = Parser::new(source);
+            let stmts = parser.parse_file().unwrap();
+            assert!(matches!(stmts[..], [Stmt::CreateVar{data: None, ..}]), "{source:?}");
+        }
+
+        assert_eq!(parse_err("const x\n"), ErrorType::ConstWithoutValue);
+    }
+}
@@ -0,0 +1,197 @@
+//! An interactive prompt, started with `--repl`. Each input is parsed, type checked, compiled, and
+//! ran on the VM. Variables, enums, and interned names carry over between inputs.
+
+
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    mem,
+};
+use string_interner::StringInterner;
+use test_lang_vm::{
+    ModuleId,
+    Value,
+};
+use crate::{
+    ast::*,
+    compiler::Session,
+    error::*,
+    lexer::bracket_depths,
+    parser::Parser,
+    static_analysis::{
+        type_check::type_check,
+        fold::fold_constants,
+    },
+};
+
+
+/// Run the REPL until STDIN is closed
+pub fn run() {
+    let stdin = io::stdin();
+
+    let mut interner = StringInterner::new();
+    let mut session = Session::default();
+    let mut stack = Vec::new();
+    let mut input = String::new();
+
+    loop {
+        // a second prompt means the input so far isn't finished. It is indented by the depth of
+        // the unclosed brackets.
+        if input.is_empty() {
+            print!("> ");
+        } else {
+            let (_, depth) = bracket_depths(&input)
+                .pop()
+                .unwrap_or_default();
+            // extra closing brackets are a syntax error, so don't indent for them
+            print!("... {}", "    ".repeat(depth.max(0) as usize));
+        }
+        io::stdout().flush().ok();
+
+        match stdin.read_line(&mut input) {
+            Ok(0)=>{
+                println!();
+                break;
+            },
+            Ok(_)=>{},
+            Err(e)=>{
+                eprintln!("Could not read the input: {e}");
+                break;
+            },
+        }
+
+        let (mut parser, _) = Parser::with_interner(&input, mem::take(&mut interner));
+        let result = parser.parse_file();
+        interner = mem::take(&mut parser.lexer.extras);
+
+        let mut stmts = match result {
+            Ok(stmts)=>stmts,
+            // wait for the rest of the input
+            Err(e) if is_incomplete(&e)=>continue,
+            Err(e)=>{
+                e.print(&input);
+                input.clear();
+                continue;
+            },
+        };
+
+        let mut errors = mem::take(&mut parser.non_fatal_errors);
+        errors.extend(type_check(&stmts, &interner));
+        // unused variables aren't checked, since a later line can still use them
+
+        let failed = errors
+            .iter()
+            .any(|err|!err.err_type().is_warning());
+        for err in dedup_errors(errors) {
+            err.print(&input);
+        }
+
+        if !failed {
+            fold_constants(&mut stmts);
+            run_input(&mut session, &mut stack, print_expressions(stmts), &input);
+        }
+
+        input.clear();
+    }
+}
+
+/// Compile and run one input. If anything fails, the variables it created are dropped.
+fn run_input(session: &mut Session, stack: &mut Vec<Value>, stmts: Vec<Stmt>, source: &str) {
+    let previous = session.clone();
+
+    let module = match session.compile(&stmts, ModuleId(0), "repl") {
+        Ok(module)=>module,
+        Err(e)=>{
+            e.print(source);
+            return;
+        },
+    };
+
+    // the top level variables are the first values on the stack, so the base is always 0
+    if let Err(e) = module.run(0, 0, stack) {
+        e.print(source);
+
+        *session = previous;
+        stack.truncate(session.local_count());
+    }
+}
+
+/// Print the value of bare expressions on their own line instead of discarding it
+fn print_expressions(stmts: Vec<Stmt>)->Vec<Stmt> {
+    let mut out = Vec::with_capacity(stmts.len());
+
+    for stmt in stmts {
+        match stmt {
+            Stmt::Expression(span, expr)=>out.push(Stmt::Println(span, expr)),
+            stmt=>out.push(stmt),
+        }
+    }
+
+    return out;
+}
+
+/// Errors that mean the input ended before something was closed
+fn is_incomplete(err: &Error)->bool {
+    use ErrorType::*;
+    matches!(err.err_type(), UnclosedCurly|UnclosedParen|UnclosedSquare|UnclosedString|UnclosedComment)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str, interner: &mut StringInterner)->Result<Vec<Stmt>, Error> {
+        let (mut parser, _) = Parser::with_interner(source, mem::take(interner));
+        let result = parser.parse_file();
+        *interner = mem::take(&mut parser.lexer.extras);
+
+        return result;
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let mut interner = StringInterner::new();
+        let incomplete = |source, interner: &mut StringInterner|is_incomplete(&parse(source, interner).unwrap_err());
+
+        assert!(incomplete("if true {\n", &mut interner));
+        assert!(incomplete("let x = (1 +\n", &mut interner));
+        assert!(incomplete("let x = [1,\n", &mut interner));
+        assert!(incomplete("let s = \"abc\n", &mut interner));
+        assert!(incomplete("/* a comment\n", &mut interner));
+        assert!(!incomplete("let x = )\n", &mut interner));
+    }
+
+    #[test]
+    fn bare_expressions_are_printed() {
+        let mut interner = StringInterner::new();
+        let stmts = print_expressions(parse("let x = 1\nx + 1\nprint x\n", &mut interner).unwrap());
+
+        assert!(matches!(stmts[..], [Stmt::CreateVar{..}, Stmt::Println(..), Stmt::Print(..)]));
+    }
+
+    #[test]
+    fn failed_input_is_rolled_back() {
+        let mut interner = StringInterner::new();
+        let mut session = Session::default();
+        let mut stack = Vec::new();
+
+        let source = "let a = 1\n";
+        run_input(&mut session, &mut stack, parse(source, &mut interner).unwrap(), source);
+        assert_eq!(stack, [Value::Integer(1)]);
+
+        // `b` is created before the division fails, so it has to be dropped again
+        let source = "let b = 2\nlet c = a / 0\n";
+        run_input(&mut session, &mut stack, parse(source, &mut interner).unwrap(), source);
+        assert_eq!(session.local_count(), 1);
+        assert_eq!(stack, [Value::Integer(1)]);
+
+        // `a` still works after the failed input
+        let source = "let d = a + 1\n";
+        run_input(&mut session, &mut stack, parse(source, &mut interner).unwrap(), source);
+        assert_eq!(session.local_count(), 2);
+        assert_eq!(stack, [Value::Integer(1), Value::Integer(2)]);
+    }
+}
@@ -36,20 +36,42 @@ pub enum Stmt {
         span: Span,
         condition: ExprId,
         block: BlockId,
+        else_block: Option<BlockId>,
+    },
+    For {
+        span: Span,
+        slot: VarSlot,
+        iterable: ExprId,
+        block: BlockId,
+        else_block: Option<BlockId>,
+    },
+    Match {
+        span: Span,
+        scrutinee: ExprId,
+        arms: Vec<(ExprId, BlockId)>,
+        default: Option<BlockId>,
+    },
+    Enum {
+        span: Span,
+        name: Symbol,
+        /// each variant and its explicit value, if it has one
+        items: Vec<(Symbol, Option<i64>)>,
     },
     If {
         span: Span,
         conditions: Vec<(ExprId, BlockId)>,
         default: Option<BlockId>,
     },
-    Class(Span, Class),
+    Class(Span, ClassId),
     Expression(Span, ExprId),
     Return(Span, Option<ExprId>),
     Continue(Span),
     Break(Span),
     Print(Span, ExprId),
+    Println(Span, ExprId),
     Function(Span, FunctionId),
     DeleteVar(Span, VarSlot),
+    Block(BlockId),
 }
 
 /// Each one of these is assigned to an SSA variable and used exactly once.
@@ -68,21 +90,40 @@ pub enum SSAExpr {
     BinaryOp(SSAId, BinaryOp, SSAId),
     UnaryOp(UnaryOp, SSAId),
     Call(SSAId, Vec<SSAId>),
+    MethodCall(SSAId, Symbol, Vec<SSAId>),
     Index(SSAId, SSAId),
+    /// the bool is true for `..=`
+    Range(SSAId, SSAId, bool),
     Field(SSAId, Symbol),
+    Spread(SSAId),
+    /// Only one of the branches is evaluated, so they are separate expressions
+    Conditional(SSAId, ExprId, ExprId),
+    /// Convert each part to a string and join them
+    StringInterp(Vec<SSAId>),
 
     // Misc
+    /// An anonymous function and the variables it captures. Each one is copied into the matching
+    /// slot in the function's `captures`.
+    Closure(FunctionId, Vec<VarSlot>),
     VarSlot(VarSlot),
+    Copy(VarSlot),
+    Ref(Permissions, VarSlot),
+    /// A name that isn't a variable, like a function or class
+    Named(Symbol),
+    /// The parent class of the method's class
+    Super,
     ExternExpr(ExprId),
     AssociatedValue(Symbol, Symbol),
 }
 
 
 pub struct AnalysisFile {
-    functions: SlotMap<FunctionId, Function>,
-    classes: SlotMap<ClassId, Class>,
-    exprs: SlotMap<ExprId, Expr>,
-    blocks: SlotMap<BlockId, Block>,
+    pub functions: SlotMap<FunctionId, Function>,
+    pub classes: SlotMap<ClassId, Class>,
+    pub exprs: SlotMap<ExprId, Expr>,
+    pub blocks: SlotMap<BlockId, Block>,
+    /// the top level of the file
+    pub root: BlockId,
 }
 
 pub struct Block {
@@ -93,11 +134,16 @@ pub struct Block {
 
 pub struct Function {
     pub span: Span,
-    pub name: Symbol,
+    /// `None` for anonymous functions
+    pub name: Option<Symbol>,
     pub id: FunctionId,
     pub func_type: FunctionType,
     pub perms: Permissions,
-    pub params: Vec<(Span, Permissions, Symbol)>,
+    pub params: Vec<(Span, Permissions, Symbol, VarSlot)>,
+    /// the last parameter collects the extra arguments into a list
+    pub variadic: bool,
+    /// the slots of the variables captured by an anonymous function
+    pub captures: Vec<VarSlot>,
     pub body: BlockId,
 }
 
@@ -106,12 +152,15 @@ pub struct Class {
     pub id: ClassId,
     pub perms: Permissions,
     pub name: Symbol,
+    pub parent: Option<Symbol>,
     pub constructor: Option<FunctionId>,
     pub fields: Vec<(Permissions, Symbol)>,
     pub methods: Vec<FunctionId>,
     pub associated: Vec<FunctionId>,
 }
 
+/// An expression flattened into SSA form. Each `SSAId` is an index into `inner_ssa`, and the last
+/// item is the value of the whole expression.
 pub struct Expr {
     pub inner_ssa: Vec<SSAExpr>,
 }
@@ -159,3 +208,7 @@ impl Key for BlockId {
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct VarSlot(usize);
+impl Key for VarSlot {
+    fn from_id(id: usize)->Self {VarSlot(id)}
+    fn get_id(&self)->usize {self.0}
+}
@@ -0,0 +1,259 @@
+//! Constant folding. Operators with only literal operands are replaced by their result, so
+//! `2 + (3 * 4)` becomes `14` before the code is compiled. Folding follows the same rules as the VM,
+//! and anything that would be an error at runtime, like dividing by zero or overflowing, is left
+//! for the VM to report.
+
+
+use logos::Span;
+use std::mem::{
+    discriminant,
+    replace,
+};
+use crate::ast::*;
+
+
+/// Fold the constant expressions in `stmts`, including inside of blocks and functions
+pub fn fold_constants(stmts: &mut [Stmt]) {
+    for stmt in stmts {
+        fold_stmt(stmt);
+    }
+}
+
+fn fold_block(block: &mut Block) {
+    fold_constants(&mut block.body);
+}
+
+fn fold_stmt(stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Function(_, func)=>fold_block(&mut func.body),
+        Stmt::Class{constructor, methods, associated, ..}=>{
+            for func in constructor.iter_mut().chain(methods).chain(associated) {
+                fold_block(&mut func.body);
+            }
+        },
+        Stmt::CreateConst{data, ..}|
+            Stmt::CreateVar{data: Some(data), ..}|
+            Stmt::SetVar{data, ..}|
+            Stmt::Expression(_, data)|
+            Stmt::Print(_, data)|
+            Stmt::Println(_, data)|
+            Stmt::Return(_, Some(data))=>fold_expr(data),
+        Stmt::If{conditions, default, ..}=>{
+            for (condition, block) in conditions {
+                fold_expr(condition);
+                fold_block(block);
+            }
+            if let Some(block) = default {
+                fold_block(block);
+            }
+        },
+        Stmt::WhileLoop{condition: expr, body, else_block, ..}|
+            Stmt::ForLoop{iterable: expr, body, else_block, ..}=>{
+                fold_expr(expr);
+                fold_block(body);
+                if let Some(block) = else_block {
+                    fold_block(block);
+                }
+            },
+        // patterns are always literals, so only the value and the blocks can fold
+        Stmt::Match{scrutinee, arms, default, ..}=>{
+            fold_expr(scrutinee);
+            for (_, block) in arms {
+                fold_block(block);
+            }
+            if let Some(block) = default {
+                fold_block(block);
+            }
+        },
+        Stmt::Block(block)=>fold_block(block),
+        Stmt::Enum{..}|
+            Stmt::CreateVar{data: None, ..}|
+            Stmt::DeleteVar(..)|
+            Stmt::Return(_, None)|
+            Stmt::Continue(_)|
+            Stmt::Break(_)=>{},
+    }
+}
+
+fn fold_expr(expr: &mut Expr) {
+    // fold the operands first, so nested operations fold from the inside out
+    match expr {
+        Expr::BinaryOp(_, _, sides)|
+            Expr::Index(_, sides)|
+            Expr::Range(_, sides, _)=>{
+                fold_expr(&mut sides[0]);
+                fold_expr(&mut sides[1]);
+            },
+        Expr::Conditional(_, items)=>{
+            for item in items.iter_mut() {
+                fold_expr(item);
+            }
+        },
+        Expr::Lambda(_, lambda)=>fold_block(&mut lambda.body),
+        Expr::UnaryOp(_, _, inner)|
+            Expr::Field(_, inner, _)|
+            Expr::Spread(_, inner)=>fold_expr(inner),
+        Expr::MethodCall(_, _, items)|
+            Expr::AssociatedCall(_, _, items)|
+            Expr::Call(_, items)|
+            Expr::List(_, items)=>{
+                for item in items {
+                    fold_expr(item);
+                }
+            },
+        Expr::Object(_, fields)=>{
+            for (_, _, field) in fields {
+                fold_expr(field);
+            }
+        },
+        Expr::StringInterp(_, parts)=>{
+            for part in parts {
+                if let StringPart::Expr(expr) = part {
+                    fold_expr(expr);
+                }
+            }
+        },
+        _=>{},
+    }
+
+    let folded = match expr {
+        Expr::BinaryOp(span, op, sides)=>fold_binary(span.clone(), *op, &sides[0], &sides[1]),
+        Expr::UnaryOp(span, op, inner)=>fold_unary(span.clone(), *op, inner),
+        // a literal condition always takes the same branch
+        Expr::Conditional(_, items)=>match items[0] {
+            Expr::Bool(_, condition)=>{
+                let branch = if condition {1} else {2};
+                Some(replace(&mut items[branch], Expr::Bool(0..0, false)))
+            },
+            _=>None,
+        },
+        _=>None,
+    };
+
+    if let Some(folded) = folded {
+        *expr = folded;
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq)]
+enum Literal {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+fn literal(expr: &Expr)->Option<Literal> {
+    match expr {
+        Expr::Integer(_, i)=>Some(Literal::Int(*i)),
+        Expr::Float(_, f)=>Some(Literal::Float(*f)),
+        Expr::Bool(_, b)=>Some(Literal::Bool(*b)),
+        _=>None,
+    }
+}
+
+/// Apply a binary operator to two literals. Returns `None` if it can't be folded.
+fn fold_binary(span: Span, op: BinaryOp, left: &Expr, right: &Expr)->Option<Expr> {
+    use Literal as L;
+    use BinaryOp as B;
+
+    // integers are promoted to floats when the other side is a float
+    let (left, right) = match (literal(left)?, literal(right)?) {
+        (L::Int(l), L::Float(r))=>(L::Float(l as f64), L::Float(r)),
+        (L::Float(l), L::Int(r))=>(L::Float(l), L::Float(r as f64)),
+        sides=>sides,
+    };
+
+    let folded = match (op, left, right) {
+        // comparing different types is left to the VM
+        (B::Equal, l, r) if discriminant(&l) == discriminant(&r)=>Expr::Bool(span, l == r),
+        (B::NotEqual, l, r) if discriminant(&l) == discriminant(&r)=>Expr::Bool(span, l != r),
+
+        // the checked methods return `None` for overflow and division by zero
+        (B::Add, L::Int(l), L::Int(r))=>Expr::Integer(span, l.checked_add(r)?),
+        (B::Sub, L::Int(l), L::Int(r))=>Expr::Integer(span, l.checked_sub(r)?),
+        (B::Mul, L::Int(l), L::Int(r))=>Expr::Integer(span, l.checked_mul(r)?),
+        (B::Div, L::Int(l), L::Int(r))=>Expr::Integer(span, l.checked_div(r)?),
+        (B::Mod, L::Int(l), L::Int(r))=>Expr::Integer(span, l.checked_rem(r)?),
+        (B::Less, L::Int(l), L::Int(r))=>Expr::Bool(span, l < r),
+        (B::Greater, L::Int(l), L::Int(r))=>Expr::Bool(span, l > r),
+        (B::LessEqual, L::Int(l), L::Int(r))=>Expr::Bool(span, l <= r),
+        (B::GreaterEqual, L::Int(l), L::Int(r))=>Expr::Bool(span, l >= r),
+        (B::BitAnd, L::Int(l), L::Int(r))=>Expr::Integer(span, l & r),
+        (B::BitOr, L::Int(l), L::Int(r))=>Expr::Integer(span, l | r),
+        (B::BitXor, L::Int(l), L::Int(r))=>Expr::Integer(span, l ^ r),
+        // the VM reports shifts by a negative amount or by 64 or more
+        (B::Shl, L::Int(l), L::Int(r))=>Expr::Integer(span, l.checked_shl(u32::try_from(r).ok()?)?),
+        (B::Shr, L::Int(l), L::Int(r))=>Expr::Integer(span, l.checked_shr(u32::try_from(r).ok()?)?),
+
+        (B::Add, L::Float(l), L::Float(r))=>Expr::Float(span, l + r),
+        (B::Sub, L::Float(l), L::Float(r))=>Expr::Float(span, l - r),
+        (B::Mul, L::Float(l), L::Float(r))=>Expr::Float(span, l * r),
+        (B::Div, L::Float(l), L::Float(r)) if r != 0.0=>Expr::Float(span, l / r),
+        (B::Mod, L::Float(l), L::Float(r)) if r != 0.0=>Expr::Float(span, l % r),
+        (B::Less, L::Float(l), L::Float(r))=>Expr::Bool(span, l < r),
+        (B::Greater, L::Float(l), L::Float(r))=>Expr::Bool(span, l > r),
+        (B::LessEqual, L::Float(l), L::Float(r))=>Expr::Bool(span, l <= r),
+        (B::GreaterEqual, L::Float(l), L::Float(r))=>Expr::Bool(span, l >= r),
+
+        (B::LogicAnd, L::Bool(l), L::Bool(r))=>Expr::Bool(span, l && r),
+        (B::LogicOr, L::Bool(l), L::Bool(r))=>Expr::Bool(span, l || r),
+
+        _=>return None,
+    };
+
+    return Some(folded);
+}
+
+/// Apply a unary operator to a literal. Returns `None` if it can't be folded.
+fn fold_unary(span: Span, op: UnaryOp, inner: &Expr)->Option<Expr> {
+    let folded = match (op, literal(inner)?) {
+        (UnaryOp::Negate, Literal::Int(i))=>Expr::Integer(span, i.checked_neg()?),
+        (UnaryOp::Negate, Literal::Float(f))=>Expr::Float(span, -f),
+        (UnaryOp::Not, Literal::Bool(b))=>Expr::Bool(span, !b),
+        (UnaryOp::BitNot, Literal::Int(i))=>Expr::Integer(span, !i),
+        _=>return None,
+    };
+
+    return Some(folded);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use super::*;
+
+    /// parse `source` as the value of a variable and fold it
+    fn fold(source: &str)->Expr {
+        let source = format!("let x = {source}\n");
+        let (mut parser, _) = Parser::new(&source);
+        let mut stmts = parser.parse_file().unwrap();
+        fold_constants(&mut stmts);
+
+        match stmts.pop() {
+            Some(Stmt::CreateVar{data: Some(data), ..})=>data,
+            _=>panic!("Expected a variable"),
+        }
+    }
+
+    #[test]
+    fn folds_nested_operations() {
+        assert!(matches!(fold("2 + 3 * 4"), Expr::Integer(_, 14)));
+        assert!(matches!(fold("1 + 2.5"), Expr::Float(_, f) if f == 3.5));
+        assert!(matches!(fold("!(1 < 2)"), Expr::Bool(_, false)));
+    }
+
+    #[test]
+    fn overflow_is_not_folded() {
+        assert!(matches!(fold("9223372036854775807 + 1"), Expr::BinaryOp(_, BinaryOp::Add, _)));
+        assert!(matches!(fold("-(-9223372036854775807 - 1)"), Expr::UnaryOp(_, UnaryOp::Negate, _)));
+    }
+
+    #[test]
+    fn division_by_zero_is_not_folded() {
+        assert!(matches!(fold("1 / 0"), Expr::BinaryOp(_, BinaryOp::Div, _)));
+        assert!(matches!(fold("1 % 0"), Expr::BinaryOp(_, BinaryOp::Mod, _)));
+        assert!(matches!(fold("1.0 / 0.0"), Expr::BinaryOp(_, BinaryOp::Div, _)));
+    }
+}
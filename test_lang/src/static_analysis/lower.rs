@@ -0,0 +1,599 @@
+//! Lowers the parser AST into the SSA form in `static_analysis::ast`. Every variable declaration
+//! gets its own `VarSlot`, so shadowed variables with the same name are different slots, and each
+//! expression is flattened into a list of `SSAExpr`s.
+//!
+//! Lowering expects code that passed the type checker. Names that don't resolve to a variable are
+//! assumed to be functions or classes and become `SSAExpr::Named`.
+//!
+//! Anonymous functions capture the outer variables they use. Each captured variable gets a new slot
+//! inside the function, and the `SSAExpr::Closure` lists the outer slots copied into them.
+
+
+use string_interner::DefaultSymbol as Symbol;
+use logos::Span;
+use crate::ast::{
+    self,
+    EnumItem,
+    FunctionType,
+    Permissions,
+    StringPart,
+};
+use super::{
+    ast::*,
+    utils::*,
+};
+
+
+/// Lower `stmts` into an `AnalysisFile`. The statements are put in the file's `root` block.
+pub fn lower(stmts: &[ast::Stmt])->AnalysisFile {
+    let mut blocks = SlotMap::new();
+    let root = blocks.insert(Block {
+        parent: None,
+        children: Vec::new(),
+        body: Vec::new(),
+    });
+
+    let mut lowerer = Lowerer {
+        file: AnalysisFile {
+            functions: SlotMap::new(),
+            classes: SlotMap::new(),
+            exprs: SlotMap::new(),
+            blocks,
+            root,
+        },
+        frames: vec![Frame {
+            scopes: vec![Vec::new()],
+            captures: None,
+        }],
+        next_slot: 0,
+    };
+
+    let body = lowerer.lower_stmts(stmts, root);
+    lowerer.file.blocks[root].body = body;
+
+    return lowerer.file;
+}
+
+
+/// The variables of a function that is being lowered
+struct Frame {
+    /// the variables visible in each nested block, innermost last
+    scopes: Vec<Vec<(Symbol, VarSlot)>>,
+    /// `Some` for anonymous functions, with the outer and inner slot of each captured variable
+    captures: Option<Vec<(VarSlot, VarSlot)>>,
+}
+
+struct Lowerer {
+    file: AnalysisFile,
+    /// the function being lowered and the functions it is nested in, innermost last
+    frames: Vec<Frame>,
+    next_slot: usize,
+}
+impl Lowerer {
+    fn scopes(&mut self)->&mut Vec<Vec<(Symbol, VarSlot)>> {
+        &mut self.frames.last_mut().unwrap().scopes
+    }
+
+    fn new_slot(&mut self)->VarSlot {
+        let slot = VarSlot::from_id(self.next_slot);
+        self.next_slot += 1;
+
+        return slot;
+    }
+
+    fn declare(&mut self, name: Symbol)->VarSlot {
+        let slot = self.new_slot();
+        self.scopes().last_mut().unwrap().push((name, slot));
+
+        return slot;
+    }
+
+    fn resolve(&mut self, name: Symbol)->Option<VarSlot> {
+        self.resolve_in(self.frames.len() - 1, name)
+    }
+
+    /// Resolve `name` in the function at `frame`. Anonymous functions capture the variable if it
+    /// belongs to an outer function.
+    fn resolve_in(&mut self, frame: usize, name: Symbol)->Option<VarSlot> {
+        let slot = self.frames[frame].scopes
+            .iter()
+            .rev()
+            .flat_map(|scope|scope.iter().rev())
+            .find(|(var, _)|*var == name)
+            .map(|(_, slot)|*slot);
+        if slot.is_some() {
+            return slot;
+        }
+
+        if frame == 0 || self.frames[frame].captures.is_none() {
+            return None;
+        }
+
+        let outer = self.resolve_in(frame - 1, name)?;
+        let inner = self.new_slot();
+
+        let frame = &mut self.frames[frame];
+        frame.scopes[0].push((name, inner));
+        frame.captures.as_mut().unwrap().push((outer, inner));
+
+        return Some(inner);
+    }
+
+    /// Resolve a variable that is assigned to or deleted. Undefined variables are reported by the
+    /// type checker, so they just get a new slot here.
+    fn resolve_or_new(&mut self, name: Symbol)->VarSlot {
+        match self.resolve(name) {
+            Some(slot)=>slot,
+            None=>self.new_slot(),
+        }
+    }
+
+    fn lower_stmts(&mut self, stmts: &[ast::Stmt], block: BlockId)->Vec<Stmt> {
+        stmts
+            .iter()
+            .map(|stmt|self.lower_stmt(stmt, block))
+            .collect()
+    }
+
+    /// Lower a block with its own scope as a child of `parent`
+    fn lower_block(&mut self, block: &ast::Block, parent: BlockId)->BlockId {
+        self.scopes().push(Vec::new());
+        let id = self.lower_block_inner(&block.body, Some(parent));
+        self.scopes().pop();
+
+        return id;
+    }
+
+    fn lower_block_inner(&mut self, stmts: &[ast::Stmt], parent: Option<BlockId>)->BlockId {
+        // insert the block first so its children can refer to it
+        let id = self.file.blocks.insert(Block {
+            parent,
+            children: Vec::new(),
+            body: Vec::new(),
+        });
+        if let Some(parent) = parent {
+            self.file.blocks[parent].children.push(id);
+        }
+
+        let body = self.lower_stmts(stmts, id);
+        self.file.blocks[id].body = body;
+
+        return id;
+    }
+
+    fn lower_function(&mut self, func: &ast::Function)->FunctionId {
+        // functions can't see the variables of the scope they are defined in
+        self.frames.push(Frame {
+            scopes: vec![Vec::new()],
+            captures: None,
+        });
+
+        let params = func.params
+            .iter()
+            .map(|(span, perms, name, _)|(span.clone(), *perms, *name, self.declare(*name)))
+            .collect();
+        let body = self.lower_block_inner(&func.body.body, None);
+
+        self.frames.pop();
+
+        return self.file.functions.insert_with(|id|Function {
+            span: func.span.clone(),
+            name: Some(func.name),
+            id: *id,
+            func_type: func.func_type,
+            perms: func.permissions,
+            params,
+            variadic: func.variadic,
+            captures: Vec::new(),
+            body,
+        });
+    }
+
+    /// Lower an anonymous function. Returns the function and the outer slots it captures.
+    fn lower_lambda(&mut self, span: Span, lambda: &ast::Lambda)->(FunctionId, Vec<VarSlot>) {
+        self.frames.push(Frame {
+            scopes: vec![Vec::new()],
+            captures: Some(Vec::new()),
+        });
+
+        let params = lambda.params
+            .iter()
+            .map(|(span, perms, name, _)|(span.clone(), *perms, *name, self.declare(*name)))
+            .collect();
+        let body = self.lower_block_inner(&lambda.body.body, None);
+
+        let (outer, captures) = self.frames
+            .pop()
+            .unwrap()
+            .captures
+            .unwrap()
+            .into_iter()
+            .unzip();
+
+        let id = self.file.functions.insert_with(|id|Function {
+            span,
+            name: None,
+            id: *id,
+            func_type: FunctionType::Normal,
+            perms: Permissions::empty(),
+            params,
+            variadic: lambda.variadic,
+            captures,
+            body,
+        });
+
+        return (id, outer);
+    }
+
+    fn lower_stmt(&mut self, stmt: &ast::Stmt, block: BlockId)->Stmt {
+        match stmt {
+            ast::Stmt::Function(span, func)=>Stmt::Function(span.clone(), self.lower_function(func)),
+            ast::Stmt::Class{span, permissions, name, parent, constructor, fields, methods, associated, ..}=>{
+                let constructor = constructor
+                    .as_ref()
+                    .map(|func|self.lower_function(func));
+                let methods = methods
+                    .iter()
+                    .map(|func|self.lower_function(func))
+                    .collect();
+                let associated = associated
+                    .iter()
+                    .map(|func|self.lower_function(func))
+                    .collect();
+
+                let id = self.file.classes.insert_with(|id|Class {
+                    span: span.clone(),
+                    id: *id,
+                    perms: *permissions,
+                    name: *name,
+                    parent: *parent,
+                    constructor,
+                    fields: fields.clone(),
+                    methods,
+                    associated,
+                });
+
+                Stmt::Class(span.clone(), id)
+            },
+            ast::Stmt::Enum{span, name, items}=>Stmt::Enum {
+                span: span.clone(),
+                name: *name,
+                items: items
+                    .iter()
+                    .map(|item|match item {
+                        EnumItem::Name(_, name)=>(*name, None),
+                        EnumItem::NameValue(_, name, value)=>(*name, Some(*value)),
+                    })
+                    .collect(),
+            },
+            // the value is lowered before the variable is declared, so `let x = x` refers to the
+            // outer `x`
+            ast::Stmt::CreateConst{span, name, data, ..}=>{
+                let init = self.lower_expr(data);
+                Stmt::CreateConst {
+                    span: span.clone(),
+                    name: *name,
+                    slot: self.declare(*name),
+                    init,
+                }
+            },
+            ast::Stmt::CreateVar{span, var_type, name, data, ..}=>{
+                let init = data
+                    .as_ref()
+                    .map(|data|self.lower_expr(data));
+                Stmt::CreateVar {
+                    span: span.clone(),
+                    perms: *var_type,
+                    name: *name,
+                    slot: self.declare(*name),
+                    init,
+                }
+            },
+            ast::Stmt::SetVar{span, left, data}=>Stmt::SetVar {
+                span: span.clone(),
+                name: left[0],
+                slot: self.resolve_or_new(left[0]),
+                fields: left[1..].to_vec(),
+                data: self.lower_expr(data),
+            },
+            ast::Stmt::DeleteVar(span, name)=>{
+                let slot = self.resolve_or_new(*name);
+
+                // the name refers to an outer variable again after it is deleted
+                for scope in self.scopes().iter_mut().rev() {
+                    if let Some(i) = scope.iter().rposition(|(_, s)|*s == slot) {
+                        scope.remove(i);
+                        break;
+                    }
+                }
+
+                Stmt::DeleteVar(span.clone(), slot)
+            },
+            ast::Stmt::If{span, conditions, default}=>Stmt::If {
+                span: span.clone(),
+                conditions: conditions
+                    .iter()
+                    .map(|(condition, body)|(self.lower_expr(condition), self.lower_block(body, block)))
+                    .collect(),
+                default: default
+                    .as_ref()
+                    .map(|body|self.lower_block(body, block)),
+            },
+            ast::Stmt::WhileLoop{span, condition, body, else_block}=>Stmt::While {
+                span: span.clone(),
+                condition: self.lower_expr(condition),
+                block: self.lower_block(body, block),
+                else_block: else_block
+                    .as_ref()
+                    .map(|body|self.lower_block(body, block)),
+            },
+            ast::Stmt::ForLoop{span, binding, iterable, body, else_block}=>{
+                let iterable = self.lower_expr(iterable);
+
+                // the binding is only visible in the loop body
+                self.scopes().push(Vec::new());
+                let slot = self.declare(*binding);
+                let body = self.lower_block(body, block);
+                self.scopes().pop();
+
+                Stmt::For {
+                    span: span.clone(),
+                    slot,
+                    iterable,
+                    block: body,
+                    else_block: else_block
+                        .as_ref()
+                        .map(|body|self.lower_block(body, block)),
+                }
+            },
+            ast::Stmt::Match{span, scrutinee, arms, default}=>Stmt::Match {
+                span: span.clone(),
+                scrutinee: self.lower_expr(scrutinee),
+                arms: arms
+                    .iter()
+                    .map(|(pattern, body)|(self.lower_expr(pattern), self.lower_block(body, block)))
+                    .collect(),
+                default: default
+                    .as_ref()
+                    .map(|body|self.lower_block(body, block)),
+            },
+            ast::Stmt::Block(body)=>Stmt::Block(self.lower_block(body, block)),
+            ast::Stmt::Expression(span, expr)=>Stmt::Expression(span.clone(), self.lower_expr(expr)),
+            ast::Stmt::Print(span, expr)=>Stmt::Print(span.clone(), self.lower_expr(expr)),
+            ast::Stmt::Println(span, expr)=>Stmt::Println(span.clone(), self.lower_expr(expr)),
+            ast::Stmt::Return(span, expr)=>Stmt::Return(
+                span.clone(),
+                expr.as_ref().map(|expr|self.lower_expr(expr)),
+            ),
+            ast::Stmt::Continue(span)=>Stmt::Continue(span.clone()),
+            ast::Stmt::Break(span)=>Stmt::Break(span.clone()),
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &ast::Expr)->ExprId {
+        let mut inner_ssa = Vec::new();
+        self.flatten_expr(expr, &mut inner_ssa);
+
+        return self.file.exprs.insert(Expr {inner_ssa});
+    }
+
+    /// Push the operands of `expr`, then `expr` itself, and return the id of `expr`
+    fn flatten_expr(&mut self, expr: &ast::Expr, out: &mut Vec<SSAExpr>)->SSAId {
+        let ssa = match expr {
+            ast::Expr::Integer(_, i)=>SSAExpr::Integer(*i),
+            ast::Expr::Float(_, f)=>SSAExpr::Float(*f),
+            ast::Expr::String(_, s)=>SSAExpr::String(s.clone()),
+            ast::Expr::Bool(_, b)=>SSAExpr::Bool(*b),
+            ast::Expr::Super(_)=>SSAExpr::Super,
+            ast::Expr::Named(_, name)=>match self.resolve(*name) {
+                Some(slot)=>SSAExpr::VarSlot(slot),
+                None=>SSAExpr::Named(*name),
+            },
+            ast::Expr::Copy(_, name)=>match self.resolve(*name) {
+                Some(slot)=>SSAExpr::Copy(slot),
+                None=>SSAExpr::Named(*name),
+            },
+            ast::Expr::Ref(_, perms, name)=>match self.resolve(*name) {
+                Some(slot)=>SSAExpr::Ref(*perms, slot),
+                None=>SSAExpr::Named(*name),
+            },
+            ast::Expr::BinaryOp(_, op, sides)=>{
+                let left = self.flatten_expr(&sides[0], out);
+                let right = self.flatten_expr(&sides[1], out);
+                SSAExpr::BinaryOp(left, *op, right)
+            },
+            ast::Expr::UnaryOp(_, op, inner)=>SSAExpr::UnaryOp(*op, self.flatten_expr(inner, out)),
+            ast::Expr::Index(_, sides)=>{
+                let left = self.flatten_expr(&sides[0], out);
+                let index = self.flatten_expr(&sides[1], out);
+                SSAExpr::Index(left, index)
+            },
+            ast::Expr::Lambda(span, lambda)=>{
+                let (id, captures) = self.lower_lambda(span.clone(), lambda);
+                SSAExpr::Closure(id, captures)
+            },
+            ast::Expr::Range(_, ends, inclusive)=>{
+                let start = self.flatten_expr(&ends[0], out);
+                let end = self.flatten_expr(&ends[1], out);
+                SSAExpr::Range(start, end, *inclusive)
+            },
+            ast::Expr::Field(_, inner, name)=>SSAExpr::Field(self.flatten_expr(inner, out), *name),
+            ast::Expr::Spread(_, inner)=>SSAExpr::Spread(self.flatten_expr(inner, out)),
+            ast::Expr::Conditional(_, items)=>{
+                let condition = self.flatten_expr(&items[0], out);
+                SSAExpr::Conditional(condition, self.lower_expr(&items[1]), self.lower_expr(&items[2]))
+            },
+            // the first item is the receiver
+            ast::Expr::MethodCall(_, name, items)=>{
+                let receiver = self.flatten_expr(&items[0], out);
+                let args = self.flatten_exprs(&items[1..], out);
+                SSAExpr::MethodCall(receiver, *name, args)
+            },
+            // the first item is the function
+            ast::Expr::Call(_, items)=>{
+                let func = self.flatten_expr(&items[0], out);
+                let args = self.flatten_exprs(&items[1..], out);
+                SSAExpr::Call(func, args)
+            },
+            // a call to a name, like `fib(n)`
+            ast::Expr::AssociatedCall(span, name, items)=>{
+                let func = self.flatten_expr(&ast::Expr::Named(span.clone(), *name), out);
+                let args = self.flatten_exprs(items, out);
+                SSAExpr::Call(func, args)
+            },
+            ast::Expr::List(_, items)=>SSAExpr::List(self.flatten_exprs(items, out)),
+            ast::Expr::StringInterp(_, parts)=>SSAExpr::StringInterp(parts
+                .iter()
+                .map(|part|match part {
+                    StringPart::Literal(s)=>{
+                        out.push(SSAExpr::String(s.clone()));
+                        SSAId::from_id(out.len() - 1)
+                    },
+                    StringPart::Expr(expr)=>self.flatten_expr(expr, out),
+                })
+                .collect()
+            ),
+            ast::Expr::Object(_, fields)=>SSAExpr::Object(fields
+                .iter()
+                .map(|(_, name, field)|(*name, self.flatten_expr(field, out)))
+                .collect()
+            ),
+            ast::Expr::AssociatedValue(_, left, right)=>SSAExpr::AssociatedValue(*left, *right),
+        };
+
+        out.push(ssa);
+
+        return SSAId::from_id(out.len() - 1);
+    }
+
+    fn flatten_exprs(&mut self, exprs: &[ast::Expr], out: &mut Vec<SSAExpr>)->Vec<SSAId> {
+        exprs
+            .iter()
+            .map(|expr|self.flatten_expr(expr, out))
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::BinaryOp,
+        parser::Parser,
+    };
+    use super::*;
+
+    fn lower_source(source: &str)->AnalysisFile {
+        let (mut parser, _) = Parser::new(source);
+        let stmts = parser.parse_file().unwrap();
+
+        return lower(&stmts);
+    }
+
+    /// the slots of the variables declared in `block`, in order
+    fn declared(file: &AnalysisFile, block: BlockId)->Vec<VarSlot> {
+        file.blocks[block].body
+            .iter()
+            .filter_map(|stmt|match stmt {
+                Stmt::CreateVar{slot, ..}|Stmt::CreateConst{slot, ..}=>Some(*slot),
+                _=>None,
+            })
+            .collect()
+    }
+
+    /// the value of the last expression in the initializer of the `index`th statement in `block`
+    fn init(file: &AnalysisFile, block: BlockId, index: usize)->&SSAExpr {
+        let id = match &file.blocks[block].body[index] {
+            Stmt::CreateVar{init: Some(id), ..}|Stmt::CreateConst{init: id, ..}=>*id,
+            _=>panic!("Expected a variable"),
+        };
+
+        return file.exprs[id].inner_ssa.last().unwrap();
+    }
+
+    #[test]
+    fn shadowing_gets_new_slots() {
+        let file = lower_source("let x = 1\nlet x = x\n{\nlet x = x\n}\n");
+        let slots = declared(&file, file.root);
+        assert_eq!(slots.len(), 2);
+        assert_ne!(slots[0], slots[1]);
+
+        // the value is lowered before the name is declared
+        assert!(matches!(init(&file, file.root, 1), SSAExpr::VarSlot(s) if *s == slots[0]));
+
+        let inner = file.blocks[file.root].children[0];
+        assert_eq!(file.blocks[inner].parent, Some(file.root));
+        let inner_slot = declared(&file, inner)[0];
+        assert!(!slots.contains(&inner_slot));
+        assert!(matches!(init(&file, inner, 0), SSAExpr::VarSlot(s) if *s == slots[1]));
+    }
+
+    #[test]
+    fn expressions_are_flattened() {
+        let file = lower_source("let x = 1 + 2 * 3\n");
+        let Stmt::CreateVar{init: Some(id), ..} = &file.blocks[file.root].body[0] else {
+            panic!("Expected a variable");
+        };
+        let ssa = &file.exprs[*id].inner_ssa;
+
+        assert_eq!(ssa.len(), 5);
+        assert!(matches!(ssa[0], SSAExpr::Integer(1)));
+        assert!(matches!(ssa[3], SSAExpr::BinaryOp(l, BinaryOp::Mul, r) if l.get_id() == 1 && r.get_id() == 2));
+        assert!(matches!(ssa[4], SSAExpr::BinaryOp(l, BinaryOp::Add, r) if l.get_id() == 0 && r.get_id() == 3));
+    }
+
+    #[test]
+    fn lambdas_capture_outer_variables() {
+        let file = lower_source("let x = 1\nlet y = 2\nlet f = function(a) {\nreturn a + x\n}\n");
+        let slots = declared(&file, file.root);
+
+        let SSAExpr::Closure(id, outer) = init(&file, file.root, 2) else {
+            panic!("Expected a closure");
+        };
+        // parameters are not captures, and `y` isn't used
+        assert_eq!(outer, &vec![slots[0]]);
+
+        let func = &file.functions[*id];
+        assert!(func.name.is_none());
+        assert_eq!(func.captures.len(), 1);
+        assert_ne!(func.captures[0], slots[0]);
+        assert_ne!(func.captures[0], func.params[0].3);
+    }
+
+    #[test]
+    fn nested_lambdas_capture_through_the_outer_lambda() {
+        let file = lower_source("let x = 1\nlet f = function() {\nreturn function() {\nreturn x\n}\n}\n");
+        let x = declared(&file, file.root)[0];
+
+        let SSAExpr::Closure(outer_id, outer) = init(&file, file.root, 1) else {
+            panic!("Expected a closure");
+        };
+        assert_eq!(outer, &vec![x]);
+        let outer_func = &file.functions[*outer_id];
+
+        let Stmt::Return(_, Some(ret)) = &file.blocks[outer_func.body].body[0] else {
+            panic!("Expected a return");
+        };
+        let SSAExpr::Closure(inner_id, inner) = file.exprs[*ret].inner_ssa.last().unwrap() else {
+            panic!("Expected a closure");
+        };
+        // the inner lambda captures the outer lambda's copy of `x`
+        assert_eq!(inner, &outer_func.captures);
+        assert_eq!(file.functions[*inner_id].captures.len(), 1);
+    }
+
+    #[test]
+    fn functions_do_not_capture() {
+        let file = lower_source("let x = 1\nfunction f() {\nreturn x\n}\n");
+        let Stmt::Function(_, id) = &file.blocks[file.root].body[1] else {
+            panic!("Expected a function");
+        };
+        let func = &file.functions[*id];
+        assert!(func.captures.is_empty());
+
+        let Stmt::Return(_, Some(ret)) = &file.blocks[func.body].body[0] else {
+            panic!("Expected a return");
+        };
+        assert!(matches!(file.exprs[*ret].inner_ssa.last(), Some(SSAExpr::Named(_))));
+    }
+}
@@ -1,2 +1,6 @@
 pub mod ast;
 pub mod utils;
+pub mod type_check;
+pub mod fold;
+pub mod lower;
+pub mod unused;
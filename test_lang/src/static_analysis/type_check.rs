@@ -0,0 +1,815 @@
+use fnv::FnvHashMap;
+use string_interner::{
+    DefaultSymbol as Symbol,
+    StringInterner,
+};
+use logos::Span;
+use std::fmt::{
+    Display,
+    Formatter,
+    Result as FmtResult,
+};
+use crate::{
+    ast::*,
+    error::*,
+};
+
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    String,
+    Range,
+    Unknown,
+}
+impl Type {
+    /// `Unknown` is compatible with every type
+    pub fn compatible(&self, other: &Self)->bool {
+        *self == Type::Unknown || *other == Type::Unknown || self == other
+    }
+
+    fn is_numeric(&self)->bool {
+        matches!(self, Self::Int|Self::Float|Self::Unknown)
+    }
+}
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Int=>write!(f, "Int"),
+            Self::Float=>write!(f, "Float"),
+            Self::Bool=>write!(f, "Bool"),
+            Self::String=>write!(f, "String"),
+            Self::Range=>write!(f, "Range"),
+            Self::Unknown=>write!(f, "_"),
+        }
+    }
+}
+
+
+struct Signature {
+    params: Vec<Type>,
+    return_type: Type,
+}
+
+#[derive(Copy, Clone)]
+struct Variable {
+    ty: Type,
+    /// whether every path to this point assigns the variable
+    initialized: bool,
+}
+impl Variable {
+    fn new(ty: Type)->Self {
+        Variable {ty, initialized: true}
+    }
+}
+
+/// Type checks the top level functions and statements in `stmts`. Returns all the errors found.
+///
+/// This is a gradual type checker: anything without an annotation has the `Unknown` type, which is
+/// compatible with everything, so unannotated code is never reported.
+pub fn type_check(stmts: &[Stmt], interner: &StringInterner)->Vec<Error> {
+    let mut checker = TypeChecker::new(interner);
+
+    // collect the signatures and enums first so they can be used before they are defined
+    for stmt in stmts {
+        if let Stmt::Enum{name, items, ..} = stmt {
+            checker.define_enum(*name, items);
+        }
+
+        if let Stmt::Function(_, func) = stmt {
+            // extra arguments go in the rest parameter, so only the fixed parameters are checked
+            let fixed = func.params.len() - func.variadic as usize;
+            let signature = Signature {
+                params: func.params[..fixed]
+                    .iter()
+                    .map(|(_, _, _, type_expr)|checker.resolve(type_expr.as_ref()))
+                    .collect(),
+                return_type: checker.resolve(func.return_type.as_ref()),
+            };
+
+            checker.functions.insert(func.name, signature);
+        }
+    }
+
+    checker.check_stmts(stmts);
+
+    return checker.errors;
+}
+
+
+struct TypeChecker<'a> {
+    interner: &'a StringInterner,
+    functions: FnvHashMap<Symbol, Signature>,
+    /// the variant names of each enum
+    enums: FnvHashMap<Symbol, Vec<Symbol>>,
+    /// the parent of each class that is in scope
+    classes: FnvHashMap<Symbol, Option<Symbol>>,
+    /// the parent of the class whose constructor or method is being checked
+    parent: Option<Symbol>,
+    scopes: Vec<FnvHashMap<Symbol, Variable>>,
+    return_type: Type,
+    errors: Vec<Error>,
+}
+impl<'a> TypeChecker<'a> {
+    fn new(interner: &'a StringInterner)->Self {
+        TypeChecker {
+            interner,
+            functions: FnvHashMap::default(),
+            enums: FnvHashMap::default(),
+            classes: FnvHashMap::default(),
+            parent: None,
+            scopes: vec![FnvHashMap::default()],
+            return_type: Type::Unknown,
+            errors: Vec::new(),
+        }
+    }
+
+    /// convert a type annotation to a `Type`. Types we don't know about are `Unknown`.
+    fn resolve(&self, type_expr: Option<&TypeExpr>)->Type {
+        let Some(TypeExpr::Named(_, name)) = type_expr else {
+            return Type::Unknown;
+        };
+
+        match self.interner.resolve(*name) {
+            Some("Int")=>Type::Int,
+            Some("Float")=>Type::Float,
+            Some("Bool")=>Type::Bool,
+            Some("String")=>Type::String,
+            _=>Type::Unknown,
+        }
+    }
+
+    fn mismatch(&mut self, expr: &Expr, expected: Type, got: Type) {
+        self.errors.push(Error::new(
+            expr.span(),
+            ErrorType::TypeMismatch(expected.to_string(), got.to_string()),
+        ));
+    }
+
+    fn define_enum(&mut self, name: Symbol, items: &[EnumItem]) {
+        let variants = items
+            .iter()
+            .map(EnumItem::name)
+            .collect();
+
+        self.enums.insert(name, variants);
+    }
+
+    /// whether the patterns of a match without a `_` arm cover every value. Only `Bool` and enum
+    /// values have a small enough set of values to cover, so other matches are never reported.
+    fn is_exhaustive(&self, ty: Type, arms: &[(Expr, Block)])->bool {
+        let covers = |f: &dyn Fn(&Expr)->bool|arms
+            .iter()
+            .any(|(pattern, _)|f(pattern));
+
+        if ty == Type::Bool {
+            return covers(&|p|matches!(p, Expr::Bool(_, true)))
+                && covers(&|p|matches!(p, Expr::Bool(_, false)));
+        }
+
+        let enum_name = arms
+            .iter()
+            .find_map(|(pattern, _)|match pattern {
+                Expr::AssociatedValue(_, name, _)=>Some(*name),
+                _=>None,
+            });
+
+        let Some(name) = enum_name else {
+            return true;
+        };
+        let Some(variants) = self.enums.get(&name) else {
+            return true;
+        };
+
+        return variants
+            .iter()
+            .all(|variant|covers(&|p|matches!(p, Expr::AssociatedValue(_, n, v) if *n == name && v == variant)));
+    }
+
+    /// report a parent class that isn't defined, or a class that ends up inheriting from itself
+    fn check_parent(&mut self, span: &Span, name: Symbol, parent: Symbol) {
+        if !self.classes.contains_key(&parent) {
+            self.errors.push(Error::new(span.clone(), ErrorType::UndefinedClass));
+            return;
+        }
+
+        // a longer chain than the number of classes has to repeat a class
+        let mut next = Some(parent);
+        for _ in 0..self.classes.len() {
+            match next {
+                Some(class) if class == name=>{
+                    self.errors.push(Error::new(span.clone(), ErrorType::InheritanceCycle));
+                    return;
+                },
+                Some(class)=>next = self.classes.get(&class).copied().flatten(),
+                None=>return,
+            }
+        }
+    }
+
+    fn check_initializer(&mut self, expected: Type, data: Option<&Expr>) {
+        let Some(data) = data else {
+            return;
+        };
+
+        let ty = self.check_expr(data);
+        if !expected.compatible(&ty) {
+            self.mismatch(data, expected, ty);
+        }
+    }
+
+    fn define(&mut self, name: Symbol, var: Variable) {
+        self.scopes.last_mut().unwrap().insert(name, var);
+    }
+
+    fn lookup(&self, name: Symbol)->Option<&Variable> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope|scope.get(&name))
+    }
+
+    fn lookup_mut(&mut self, name: Symbol)->Option<&mut Variable> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope|scope.get_mut(&name))
+    }
+
+    /// the type of a variable that is being read. Reading it before it is assigned is an error.
+    fn read(&mut self, span: &Span, name: Symbol)->Type {
+        let Some(var) = self.lookup(name).copied() else {
+            return Type::Unknown;
+        };
+
+        if !var.initialized {
+            self.errors.push(Error::new(span.clone(), ErrorType::VarUninitialized));
+        }
+
+        return var.ty;
+    }
+
+    fn check_block(&mut self, block: &Block) {
+        self.scopes.push(FnvHashMap::default());
+        self.check_stmts(&block.body);
+        self.scopes.pop();
+    }
+
+    /// Check blocks where only one of them runs. A variable is only initialized afterwards if every
+    /// block assigns it, and `exhaustive` is false when it is possible for none of them to run.
+    fn check_branches<'b, I: IntoIterator<Item = &'b Block>>(&mut self, blocks: I, exhaustive: bool) {
+        let before = self.scopes.clone();
+        let mut after = (!exhaustive).then(||before.clone());
+
+        for block in blocks {
+            self.scopes = before.clone();
+            self.check_block(block);
+
+            let Some(after) = &mut after else {
+                after = Some(self.scopes.clone());
+                continue;
+            };
+            for (after_scope, scope) in after.iter_mut().zip(self.scopes.iter()) {
+                for (name, var) in after_scope.iter_mut() {
+                    var.initialized &= scope[name].initialized;
+                }
+            }
+        }
+
+        self.scopes = after.unwrap_or(before);
+    }
+
+    fn check_function(&mut self, func: &Function) {
+        let mut scope = FnvHashMap::default();
+        for (_, _, name, type_expr) in func.params.iter() {
+            scope.insert(*name, Variable::new(self.resolve(type_expr.as_ref())));
+        }
+
+        let old_return = self.return_type;
+        self.return_type = self.resolve(func.return_type.as_ref());
+
+        // functions can't see the variables of the scope they are defined in
+        let old_scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+        self.check_stmts(&func.body.body);
+        self.scopes = old_scopes;
+
+        self.return_type = old_return;
+    }
+
+    /// like `check_function`, but lambdas can see the variables around them
+    fn check_lambda(&mut self, lambda: &Lambda) {
+        let mut scope = FnvHashMap::default();
+        for (_, _, name, type_expr) in lambda.params.iter() {
+            scope.insert(*name, Variable::new(self.resolve(type_expr.as_ref())));
+        }
+
+        let old_return = self.return_type;
+        self.return_type = self.resolve(lambda.return_type.as_ref());
+
+        self.scopes.push(scope);
+        self.check_stmts(&lambda.body.body);
+        self.scopes.pop();
+
+        self.return_type = old_return;
+    }
+
+    fn check_stmts(&mut self, stmts: &[Stmt]) {
+        // classes can be used before they are defined in the same block, but not outside of it
+        let old_classes = self.classes.clone();
+        for stmt in stmts {
+            if let Stmt::Class{name, parent, ..} = stmt {
+                self.classes.insert(*name, *parent);
+            }
+        }
+
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+
+        self.classes = old_classes;
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Function(_, func)=>{
+                let parent = self.parent.take();
+                self.check_function(func);
+                self.parent = parent;
+            },
+            // only the constructor and methods have a `this`, so only they can use `super`
+            Stmt::Class{span, name, parent, constructor, methods, associated, ..}=>{
+                if let Some(parent) = parent {
+                    self.check_parent(span, *name, *parent);
+                }
+
+                let old_parent = std::mem::replace(&mut self.parent, *parent);
+                for func in constructor.iter().chain(methods) {
+                    self.check_function(func);
+                }
+
+                self.parent = None;
+                for func in associated {
+                    self.check_function(func);
+                }
+                self.parent = old_parent;
+            },
+            Stmt::Enum{name, items, ..}=>self.define_enum(*name, items),
+            // variables can hold any value unless they are annotated, so the type of the
+            // initializer is only checked, not inferred
+            Stmt::CreateConst{name, type_annotation, data, ..}=>{
+                let expected = self.resolve(type_annotation.as_ref());
+                self.check_initializer(expected, Some(data));
+                self.define(*name, Variable::new(expected));
+            },
+            Stmt::CreateVar{name, type_annotation, data, ..}=>{
+                let expected = self.resolve(type_annotation.as_ref());
+                self.check_initializer(expected, data.as_ref());
+                self.define(*name, Variable {
+                    ty: expected,
+                    initialized: data.is_some(),
+                });
+            },
+            Stmt::SetVar{left, data, ..}=>{
+                let ty = self.check_expr(data);
+
+                // only check plain variables, not fields
+                if left.len() == 1 {
+                    let Some(var) = self.lookup_mut(left[0]) else {
+                        return;
+                    };
+                    var.initialized = true;
+
+                    let expected = var.ty;
+                    if !expected.compatible(&ty) {
+                        self.mismatch(data, expected, ty);
+                    }
+                }
+            },
+            // the conditions can't assign variables, so they are checked before the blocks
+            Stmt::If{conditions, default, ..}=>{
+                for (condition, _) in conditions {
+                    self.check_expr(condition);
+                }
+
+                let blocks = conditions
+                    .iter()
+                    .map(|(_, block)|block)
+                    .chain(default);
+                self.check_branches(blocks, default.is_some());
+            },
+            // the body might not run, and `break` can skip the else block
+            Stmt::WhileLoop{condition, body, else_block, ..}=>{
+                self.check_expr(condition);
+                self.check_branches([body], false);
+                if let Some(block) = else_block {
+                    self.check_branches([block], false);
+                }
+            },
+            // the binding isn't annotated, so it can be reassigned to anything
+            Stmt::ForLoop{binding, iterable, body, else_block, ..}=>{
+                self.check_expr(iterable);
+
+                self.scopes.push(FnvHashMap::default());
+                self.define(*binding, Variable::new(Type::Unknown));
+                self.check_branches([body], false);
+                self.scopes.pop();
+
+                if let Some(block) = else_block {
+                    self.check_branches([block], false);
+                }
+            },
+            Stmt::Match{span, scrutinee, arms, default}=>{
+                let ty = self.check_expr(scrutinee);
+
+                for (pattern, _) in arms {
+                    let pattern_ty = self.check_expr(pattern);
+                    let numeric = ty.is_numeric() && pattern_ty.is_numeric();
+                    if !ty.compatible(&pattern_ty) && !numeric {
+                        self.mismatch(pattern, ty, pattern_ty);
+                    }
+                }
+
+                let exhaustive = default.is_some() || self.is_exhaustive(ty, arms);
+                if !exhaustive {
+                    let span = span.start..scrutinee.span().end;
+                    self.errors.push(Error::new(span, ErrorType::NonExhaustiveMatch));
+                }
+
+                let blocks = arms
+                    .iter()
+                    .map(|(_, block)|block)
+                    .chain(default);
+                self.check_branches(blocks, exhaustive);
+            },
+            Stmt::Return(_, Some(expr))=>{
+                let ty = self.check_expr(expr);
+                let expected = self.return_type;
+                if !expected.compatible(&ty) {
+                    self.mismatch(expr, expected, ty);
+                }
+            },
+            Stmt::Block(block)=>self.check_block(block),
+            Stmt::Expression(_, expr)|
+                Stmt::Print(_, expr)|
+                Stmt::Println(_, expr)=>{
+                    self.check_expr(expr);
+                },
+            Stmt::DeleteVar(..)|
+                Stmt::Return(_, None)|
+                Stmt::Continue(_)|
+                Stmt::Break(_)=>{},
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr)->Type {
+        match expr {
+            Expr::Integer(..)=>Type::Int,
+            Expr::Float(..)=>Type::Float,
+            Expr::String(..)=>Type::String,
+            Expr::Super(span)=>{
+                if self.parent.is_none() {
+                    self.errors.push(Error::new(span.clone(), ErrorType::SuperOutsideSubclass));
+                }
+                Type::Unknown
+            },
+            // any value can be converted to a string
+            Expr::StringInterp(_, parts)=>{
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        self.check_expr(expr);
+                    }
+                }
+                Type::String
+            },
+            Expr::Bool(..)=>Type::Bool,
+            Expr::Named(_, name)|
+                Expr::Copy(_, name)=>self.read(&expr.span(), *name),
+            Expr::UnaryOp(_, op, inner)=>{
+                let ty = self.check_expr(inner);
+                let expected = match op {
+                    UnaryOp::Negate=>{
+                        if ty.is_numeric() {
+                            return ty;
+                        }
+                        Type::Int
+                    },
+                    UnaryOp::Not=>Type::Bool,
+                    UnaryOp::BitNot=>Type::Int,
+                };
+
+                if !expected.compatible(&ty) {
+                    self.mismatch(inner, expected, ty);
+                }
+
+                expected
+            },
+            Expr::BinaryOp(_, op, sides)=>{
+                let left = self.check_expr(&sides[0]);
+                let right = self.check_expr(&sides[1]);
+
+                match op {
+                    BinaryOp::LogicAnd|BinaryOp::LogicOr=>{
+                        if !Type::Bool.compatible(&left) {
+                            self.mismatch(&sides[0], Type::Bool, left);
+                        }
+                        if !Type::Bool.compatible(&right) {
+                            self.mismatch(&sides[1], Type::Bool, right);
+                        }
+                        Type::Bool
+                    },
+                    // only strings can be searched so far
+                    BinaryOp::In=>{
+                        if !Type::String.compatible(&right) {
+                            self.mismatch(&sides[1], Type::String, right);
+                        } else if !right.compatible(&left) {
+                            self.mismatch(&sides[0], right, left);
+                        }
+                        Type::Bool
+                    },
+                    // bitwise operators don't promote to `Float`
+                    BinaryOp::BitAnd|
+                        BinaryOp::BitOr|
+                        BinaryOp::BitXor|
+                        BinaryOp::Shl|
+                        BinaryOp::Shr=>{
+                            if !Type::Int.compatible(&left) {
+                                self.mismatch(&sides[0], Type::Int, left);
+                            }
+                            if !Type::Int.compatible(&right) {
+                                self.mismatch(&sides[1], Type::Int, right);
+                            }
+                            Type::Int
+                        },
+                    _=>{
+                        // an `Int` is promoted to a `Float` when the other side is a `Float`
+                        let promoted = left.is_numeric() && right.is_numeric();
+                        if !promoted && !left.compatible(&right) {
+                            self.mismatch(&sides[1], left, right);
+                        }
+
+                        match op {
+                            BinaryOp::Equal|
+                                BinaryOp::NotEqual|
+                                BinaryOp::Greater|
+                                BinaryOp::Less|
+                                BinaryOp::GreaterEqual|
+                                BinaryOp::LessEqual=>Type::Bool,
+                            _ if left == Type::Float || right == Type::Float=>Type::Float,
+                            // if either side is unknown, then the result may be the other type
+                            _=>if left == Type::Unknown {right} else {left},
+                        }
+                    },
+                }
+            },
+            Expr::AssociatedCall(_, name, args)=>{
+                let arg_types = args
+                    .iter()
+                    .map(|arg|self.check_expr(arg))
+                    .collect::<Vec<_>>();
+
+                let Some(signature) = self.functions.get(name) else {
+                    return Type::Unknown;
+                };
+
+                // we don't know which parameters a spread argument fills
+                if args.iter().any(|arg|matches!(arg, Expr::Spread(..))) {
+                    return signature.return_type;
+                }
+
+                let mut mismatches = Vec::new();
+                for ((arg, got), expected) in args.iter().zip(arg_types).zip(signature.params.iter()) {
+                    if !expected.compatible(&got) {
+                        mismatches.push((arg, *expected, got));
+                    }
+                }
+                let return_type = signature.return_type;
+
+                for (arg, expected, got) in mismatches {
+                    self.mismatch(arg, expected, got);
+                }
+
+                return_type
+            },
+            Expr::MethodCall(_, _, items)|
+                Expr::Call(_, items)|
+                Expr::List(_, items)=>{
+                    for item in items {
+                        self.check_expr(item);
+                    }
+                    Type::Unknown
+                },
+            Expr::Spread(_, inner)|
+                Expr::Field(_, inner, _)=>{
+                    self.check_expr(inner);
+                    Type::Unknown
+                },
+            Expr::Index(_, sides)=>{
+                let left = self.check_expr(&sides[0]);
+                let index = self.check_expr(&sides[1]);
+
+                if !index.compatible(&Type::Int) {
+                    self.mismatch(&sides[1], Type::Int, index);
+                }
+
+                // indexing a string gives a single character string
+                match left {
+                    Type::String=>Type::String,
+                    _=>Type::Unknown,
+                }
+            },
+            Expr::Object(_, fields)=>{
+                for (_, _, expr) in fields {
+                    self.check_expr(expr);
+                }
+                Type::Unknown
+            },
+            Expr::Ref(..)=>Type::Unknown,
+            Expr::Lambda(_, lambda)=>{
+                self.check_lambda(lambda);
+                Type::Unknown
+            },
+            Expr::Range(_, ends, _)=>{
+                for end in ends.iter() {
+                    let ty = self.check_expr(end);
+                    if !Type::Int.compatible(&ty) {
+                        self.mismatch(end, Type::Int, ty);
+                    }
+                }
+
+                Type::Range
+            },
+            Expr::Conditional(_, items)=>{
+                let condition = self.check_expr(&items[0]);
+                if !Type::Bool.compatible(&condition) {
+                    self.mismatch(&items[0], Type::Bool, condition);
+                }
+
+                let then = self.check_expr(&items[1]);
+                let otherwise = self.check_expr(&items[2]);
+                if !then.compatible(&otherwise) {
+                    self.mismatch(&items[2], then, otherwise);
+                }
+
+                // if either branch is unknown, then the result may be the other type
+                if then == Type::Unknown {otherwise} else {then}
+            },
+            // enum variants are integers. Other associated values belong to classes.
+            Expr::AssociatedValue(span, name, variant)=>match self.enums.get(name) {
+                Some(variants)=>{
+                    if !variants.contains(variant) {
+                        self.errors.push(Error::new(span.clone(), ErrorType::UndefinedEnumVariant));
+                    }
+                    Type::Int
+                },
+                None=>Type::Unknown,
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use super::*;
+
+    fn errors(source: &str)->Vec<ErrorType> {
+        let (mut parser, _) = Parser::new(source);
+        let stmts = parser.parse_file().unwrap();
+
+        type_check(&stmts, &parser.lexer.extras)
+            .into_iter()
+            .map(|err|err.err_type().clone())
+            .collect()
+    }
+
+    fn mismatch(expected: &str, got: &str)->ErrorType {
+        ErrorType::TypeMismatch(expected.to_string(), got.to_string())
+    }
+
+    #[test]
+    fn well_typed_function() {
+        let source = "function add(a: Int, b: Int): Int {\n    return a + b\n}\nprintln add(1, 2)\n";
+
+        assert_eq!(errors(source), []);
+    }
+
+    #[test]
+    fn return_type_mismatch() {
+        let source = "function f(): Int {\n    return \"one\"\n}\n";
+
+        assert_eq!(errors(source), [mismatch("Int", "String")]);
+    }
+
+    #[test]
+    fn argument_type_mismatch() {
+        let source = "function f(a: Int, b: Bool) {\n}\nf(1, 2)\n";
+
+        assert_eq!(errors(source), [mismatch("Bool", "Int")]);
+    }
+
+    #[test]
+    fn class_parents() {
+        let source = "class B : A {\n}\nclass A {\n}\nfunction f() {\n    class D : C {\n    }\n    class C {\n    }\n}\n";
+        assert_eq!(errors(source), []);
+
+        // `C` is only in scope inside of `f`
+        let source = "function f() {\n    class C {\n    }\n}\nclass D : C {\n}\n";
+        assert_eq!(errors(source), [ErrorType::UndefinedClass]);
+
+        let source = "class A : B {\n}\nclass B : A {\n}\n";
+        assert_eq!(errors(source), [ErrorType::InheritanceCycle, ErrorType::InheritanceCycle]);
+    }
+
+    #[test]
+    fn super_outside_subclass() {
+        let source = "class A {\n    f() {\n        return super.f()\n    }\n}\nclass B : A {\n    f() {\n        return super.f()\n    }\n}\n";
+
+        assert_eq!(errors(source), [ErrorType::SuperOutsideSubclass]);
+    }
+
+    #[test]
+    fn unannotated_variables_take_any_value() {
+        let source = "var x = 1\nset x = \"hi\"\nprintln x == \"a b\"\n";
+
+        assert_eq!(errors(source), []);
+    }
+
+    #[test]
+    fn annotated_variables_are_checked() {
+        let source = "let a: Int = \"s\"\nvar b: Bool = true\nset b = 1\nconst c: String = 1.5\n";
+
+        assert_eq!(errors(source), [
+            mismatch("Int", "String"),
+            mismatch("Bool", "Int"),
+            mismatch("String", "Float"),
+        ]);
+    }
+
+    #[test]
+    fn uninitialized_variables() {
+        let source = "var x
+println x
+set x = 1
+println x
+";
+        assert_eq!(errors(source), [ErrorType::VarUninitialized]);
+
+        // every branch has to assign the variable
+        let source = "var x
+if true {
+    set x = 1
+} else {
+    set x = 2
+}
+println x
+";
+        assert_eq!(errors(source), []);
+
+        let source = "var x
+if true {
+    set x = 1
+}
+println x
+";
+        assert_eq!(errors(source), [ErrorType::VarUninitialized]);
+
+        let source = "var x
+while false {
+    set x = 1
+}
+println x
+";
+        assert_eq!(errors(source), [ErrorType::VarUninitialized]);
+
+        assert_eq!(errors("let x\nprintln x\n"), [ErrorType::VarUninitialized]);
+        assert_eq!(errors("let x\nset x = 1\nprintln x\n"), []);
+    }
+
+    #[test]
+    fn exhaustive_matches() {
+        let enum_match = |arms: &str|format!("enum E {{\n    A,\n    B,\n}}\nlet e = E::A\nmatch e {{\n{arms}}}\n");
+        assert_eq!(errors(&enum_match("E::A => {\n}\nE::B => {\n}\n")), []);
+        assert_eq!(errors(&enum_match("E::A => {\n}\n")), [ErrorType::NonExhaustiveMatch]);
+        assert_eq!(errors(&enum_match("E::A => {\n}\n_ => {\n}\n")), []);
+
+        assert_eq!(errors("let b: Bool = true\nmatch b {\ntrue => {\n}\nfalse => {\n}\n}\n"), []);
+        assert_eq!(errors("let b: Bool = true\nmatch b {\ntrue => {\n}\n}\n"), [ErrorType::NonExhaustiveMatch]);
+        assert_eq!(errors("let x = 1\nmatch x {\n1 => {\n}\n_ => {\n}\n}\n"), []);
+    }
+
+    #[test]
+    fn undefined_enum_variants() {
+        assert_eq!(errors("enum E {\n    A,\n}\nlet a = E::Missing\n"), [ErrorType::UndefinedEnumVariant]);
+        assert_eq!(errors("enum E {\n    A,\n}\nlet a = E::A\n"), []);
+    }
+
+    #[test]
+    fn membership_types() {
+        assert_eq!(errors("println \"lo\" in \"hello\"\n"), []);
+        assert_eq!(errors("println 1 in \"abc\"\n"), [mismatch("String", "Int")]);
+        assert_eq!(errors("println \"a\" in 1\n"), [mismatch("String", "Int")]);
+    }
+}
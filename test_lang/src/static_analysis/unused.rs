@@ -0,0 +1,330 @@
+//! Finds variables that are never read before they go out of scope. Assigning to a variable with
+//! `set` is not a read, but a compound assignment like `set x += 1` is.
+
+
+use string_interner::{
+    DefaultSymbol as Symbol,
+    StringInterner,
+};
+use logos::Span;
+use crate::{
+    ast::*,
+    error::*,
+};
+
+
+/// Returns an `UnusedVariable` warning for each `let`, `var`, or `const` that is never read. Names
+/// starting with `_` are never reported.
+pub fn unused_variables(stmts: &[Stmt], interner: &StringInterner)->Vec<Error> {
+    let mut checker = UnusedChecker {
+        interner,
+        scopes: vec![Vec::new()],
+        warnings: Vec::new(),
+    };
+
+    checker.check_stmts(stmts);
+    checker.pop_scope();
+
+    // outer scopes are reported after inner ones, so put the warnings back in source order
+    checker.warnings.sort_by_key(|err|err.span().start);
+
+    return checker.warnings;
+}
+
+
+struct Var {
+    name: Symbol,
+    span: Span,
+    read: bool,
+}
+
+struct UnusedChecker<'a> {
+    interner: &'a StringInterner,
+    scopes: Vec<Vec<Var>>,
+    warnings: Vec<Error>,
+}
+impl<'a> UnusedChecker<'a> {
+    /// declare a variable. `span` is `None` for variables that are never reported, like parameters.
+    fn declare(&mut self, name: Symbol, span: Option<Span>) {
+        self.scopes.last_mut().unwrap().push(Var {
+            name,
+            read: span.is_none(),
+            span: span.unwrap_or(0..0),
+        });
+    }
+
+    /// mark the most recent variable named `name` as read
+    fn read(&mut self, name: Symbol) {
+        let var = self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope|scope.iter_mut().rev())
+            .find(|var|var.name == name);
+
+        if let Some(var) = var {
+            var.read = true;
+        }
+    }
+
+    fn report(&mut self, var: Var) {
+        if var.read {
+            return;
+        }
+
+        let name = self.interner
+            .resolve(var.name)
+            .unwrap_or_default();
+        if name.starts_with('_') {
+            return;
+        }
+
+        self.warnings.push(Error::new(var.span, ErrorType::UnusedVariable(name.to_string())));
+    }
+
+    fn pop_scope(&mut self) {
+        for var in self.scopes.pop().unwrap() {
+            self.report(var);
+        }
+    }
+
+    /// `delete` ends the variable's scope early
+    fn delete(&mut self, name: Symbol) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(i) = scope.iter().rposition(|var|var.name == name) {
+                let var = scope.remove(i);
+                self.report(var);
+                return;
+            }
+        }
+    }
+
+    fn check_block(&mut self, block: &Block) {
+        self.scopes.push(Vec::new());
+        self.check_stmts(&block.body);
+        self.pop_scope();
+    }
+
+    fn check_function(&mut self, func: &Function) {
+        // functions can't see the variables of the scope they are defined in
+        let old_scopes = std::mem::replace(&mut self.scopes, vec![Vec::new()]);
+
+        for (_, _, name, _) in func.params.iter() {
+            self.declare(*name, None);
+        }
+        self.check_block(&func.body);
+
+        self.pop_scope();
+        self.scopes = old_scopes;
+    }
+
+    fn check_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Function(_, func)=>self.check_function(func),
+            Stmt::Class{constructor, methods, associated, ..}=>{
+                for func in constructor.iter().chain(methods).chain(associated) {
+                    self.check_function(func);
+                }
+            },
+            Stmt::CreateConst{span, name, data, ..}=>{
+                self.check_expr(data);
+                self.declare(*name, Some(span.clone()));
+            },
+            Stmt::CreateVar{span, name, data, ..}=>{
+                if let Some(data) = data {
+                    self.check_expr(data);
+                }
+                self.declare(*name, Some(span.clone()));
+            },
+            // setting a field reads the variable holding the object
+            Stmt::SetVar{left, data, ..}=>{
+                self.check_expr(data);
+                if left.len() > 1 {
+                    self.read(left[0]);
+                }
+            },
+            Stmt::DeleteVar(_, name)=>self.delete(*name),
+            Stmt::If{conditions, default, ..}=>{
+                for (condition, block) in conditions {
+                    self.check_expr(condition);
+                    self.check_block(block);
+                }
+                if let Some(block) = default {
+                    self.check_block(block);
+                }
+            },
+            Stmt::WhileLoop{condition, body, else_block, ..}=>{
+                self.check_expr(condition);
+                self.check_block(body);
+                if let Some(block) = else_block {
+                    self.check_block(block);
+                }
+            },
+            Stmt::ForLoop{binding, iterable, body, else_block, ..}=>{
+                self.check_expr(iterable);
+
+                self.scopes.push(Vec::new());
+                self.declare(*binding, None);
+                self.check_block(body);
+                self.pop_scope();
+
+                if let Some(block) = else_block {
+                    self.check_block(block);
+                }
+            },
+            Stmt::Match{scrutinee, arms, default, ..}=>{
+                self.check_expr(scrutinee);
+                for (_, block) in arms {
+                    self.check_block(block);
+                }
+                if let Some(block) = default {
+                    self.check_block(block);
+                }
+            },
+            Stmt::Block(block)=>self.check_block(block),
+            Stmt::Expression(_, expr)|
+                Stmt::Print(_, expr)|
+                Stmt::Println(_, expr)|
+                Stmt::Return(_, Some(expr))=>self.check_expr(expr),
+            Stmt::Enum{..}|
+                Stmt::Return(_, None)|
+                Stmt::Continue(_)|
+                Stmt::Break(_)=>{},
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Named(_, name)|
+                Expr::Copy(_, name)|
+                Expr::Ref(_, _, name)=>self.read(*name),
+            Expr::BinaryOp(_, _, sides)|
+                Expr::Index(_, sides)|
+                Expr::Range(_, sides, _)=>{
+                    self.check_expr(&sides[0]);
+                    self.check_expr(&sides[1]);
+                },
+            Expr::Conditional(_, items)=>{
+                for item in items.iter() {
+                    self.check_expr(item);
+                }
+            },
+            // using a variable in a lambda counts as reading it
+            Expr::Lambda(_, lambda)=>{
+                self.scopes.push(Vec::new());
+                for (_, _, name, _) in lambda.params.iter() {
+                    self.declare(*name, None);
+                }
+                self.check_block(&lambda.body);
+                self.pop_scope();
+            },
+            Expr::UnaryOp(_, _, inner)|
+                Expr::Field(_, inner, _)|
+                Expr::Spread(_, inner)=>self.check_expr(inner),
+            // `f(x)` reads `f` when it is a variable holding a lambda
+            Expr::AssociatedCall(_, name, items)=>{
+                self.read(*name);
+                for item in items {
+                    self.check_expr(item);
+                }
+            },
+            Expr::MethodCall(_, _, items)|
+                Expr::Call(_, items)|
+                Expr::List(_, items)=>{
+                    for item in items {
+                        self.check_expr(item);
+                    }
+                },
+            Expr::Object(_, fields)=>{
+                for (_, _, field) in fields {
+                    self.check_expr(field);
+                }
+            },
+            Expr::StringInterp(_, parts)=>{
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        self.check_expr(expr);
+                    }
+                }
+            },
+            Expr::Integer(..)|
+                Expr::Float(..)|
+                Expr::String(..)|
+                Expr::Bool(..)|
+                Expr::Super(..)|
+                Expr::AssociatedValue(..)=>{},
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use super::*;
+
+    /// the names of the unused variables in `source`
+    fn unused(source: &str)->Vec<String> {
+        let (mut parser, _) = Parser::new(source);
+        let stmts = parser.parse_file().unwrap();
+
+        unused_variables(&stmts, &parser.lexer.extras)
+            .into_iter()
+            .map(|err|match err.err_type() {
+                ErrorType::UnusedVariable(name)=>name.clone(),
+                other=>panic!("Expected an unused variable, found {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unused_declarations() {
+        assert_eq!(unused("let a = 1\nvar b = 2\nconst c = 3\n"), ["a", "b", "c"]);
+        assert_eq!(unused("let a = 1\nprintln a\n"), [] as [&str; 0]);
+        // assigning is not a read, but a compound assignment is
+        assert_eq!(unused("var a = 1\nset a = 2\n"), ["a"]);
+        assert_eq!(unused("var a = 1\nset a += 2\n"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn underscore_names_are_ignored() {
+        assert_eq!(unused("let _a = 1\nvar _ = 2\nlet b = 3\n"), ["b"]);
+    }
+
+    #[test]
+    fn delete_before_read() {
+        assert_eq!(unused("let a = 1\ndelete a\n"), ["a"]);
+        // after the delete, the name refers to the outer variable again
+        assert_eq!(unused("let a = 1\n{\nlet a = 2\ndelete a\nprintln a\n}\n"), ["a"]);
+    }
+
+    #[test]
+    fn reads_inside_lambdas() {
+        assert_eq!(unused("let a = 1\nlet f = function() {\nreturn a\n}\nf()\n"), [] as [&str; 0]);
+        // parameters are never reported
+        assert_eq!(unused("let f = function(x) {\n}\nf(1)\n"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn setting_a_field_reads_the_object() {
+        assert_eq!(unused("let o = {a: 1}\nset o.a = 2\n"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn shadowing_in_nested_blocks() {
+        // the inner `a` is read, so the outer one is reported
+        assert_eq!(unused("let a = 1\n{\nlet a = 2\nprintln a\n}\n"), ["a"]);
+        let source = "let a = 1\nif true {\nlet a = 2\n}\nprintln a\n";
+        let (mut parser, _) = Parser::new(source);
+        let stmts = parser.parse_file().unwrap();
+        let warnings = unused_variables(&stmts, &parser.lexer.extras);
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(&source[warnings[0].span()], "let a = 2");
+    }
+}
@@ -74,8 +74,20 @@ impl<K: Key, T> SlotMap<K, T> {
     }
 
     pub fn insert(&mut self, data: T)->K {
+        self.insert_with(|_|data)
+    }
+
+    /// insert the data returned by `f`, which is given the key it will be stored at
+    pub fn insert_with<F: FnOnce(&K)->T>(&mut self, f: F)->K {
         let key = self.free.pop().unwrap_or(K::from_id(self.inner.len()));
-        self.inner[key.get_id()] = Some(data);
+        let data = f(&key);
+
+        let id = key.get_id();
+        if id == self.inner.len() {
+            self.inner.push(Some(data));
+        } else {
+            self.inner[id] = Some(data);
+        }
 
         return key;
     }
@@ -1,8 +1,8 @@
-use num_enum::{IntoPrimitive,FromPrimitive};
+use num_enum::{IntoPrimitive,TryFromPrimitive};
 
 
 #[repr(u8)]
-#[derive(IntoPrimitive, FromPrimitive, Default, Copy, Clone, Debug)]
+#[derive(IntoPrimitive, TryFromPrimitive, Default, Copy, Clone, Debug)]
 pub enum Instruction {
     #[default]
     Nop,
@@ -10,9 +10,10 @@ pub enum Instruction {
     // function-related
     /// Returns from a function
     Return,
-    /// Returns a value from a function
+    /// Pops a value and returns it from a function
     ReturnValue,
-    /// Calls a function. Reads the next byte to specify how many arguments the function has.
+    /// Calls a function. Reads the next byte to specify how many arguments the function has. The
+    /// arguments are popped, then the module id to call.
     Call,
 
     // data loading
@@ -22,4 +23,147 @@ pub enum Instruction {
     Constant2,
     /// Reads the next 3 bytes as an index into the constant list
     Constant3,
+    /// Reads the next byte as a local slot and pushes a copy of its value
+    GetLocal,
+    /// Reads the next byte as a local slot and pops a value into it. Setting the slot right above
+    /// the top of the stack creates it.
+    SetLocal,
+
+    // control flow
+    /// Reads the next 2 bytes as a signed offset from the end of this instruction and jumps to it
+    Jump,
+    /// Pops a bool and jumps like `Jump` if it is false
+    JumpIfFalse,
+    /// Pops a bool and jumps like `Jump` if it is true
+    JumpIfTrue,
+
+    // arithmetic. Binary operations pop the right side, then the left side, and push the result.
+    Add,
+    Sub,
+    Mul,
+    /// Integer division truncates toward zero
+    Div,
+    Mod,
+    /// Pops a number and pushes its negation
+    Negate,
+    /// Pops a bool and pushes its inverse
+    Not,
+
+    // bitwise. These only work on integers.
+    BitAnd,
+    BitOr,
+    BitXor,
+    /// Shifting by a negative amount or by 64 or more is an error
+    Shl,
+    /// An arithmetic shift, so the sign is kept
+    Shr,
+    /// Pops an integer and pushes it with every bit flipped
+    BitNot,
+
+    // comparison. These pop two values and push a bool.
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    /// Pops a string, then a value, and pushes whether the value is a substring of the string
+    In,
+
+    /// Pops an integer index, then a string, and pushes the character at that index as a string
+    Index,
+
+    // misc
+    /// Pops a value and discards it
+    Pop,
+    /// Pops a value and prints it to STDOUT without a newline
+    Print,
+    /// Reads the next byte as a count, pops that many values, and pushes them converted to
+    /// strings and joined in the order they were pushed
+    Concat,
+}
+impl Instruction {
+    /// The mnemonic used in error messages and the disassembly
+    pub fn name(&self)->&'static str {
+        match self {
+            Self::Nop=>"nop",
+            Self::Return=>"ret",
+            Self::ReturnValue=>"retVal",
+            Self::Call=>"call",
+            Self::Constant|
+                Self::Constant2|
+                Self::Constant3=>"const",
+            Self::GetLocal=>"get",
+            Self::SetLocal=>"set",
+            Self::Jump=>"jmp",
+            Self::JumpIfFalse=>"jmpf",
+            Self::JumpIfTrue=>"jmpt",
+            Self::Add=>"add",
+            Self::Sub=>"sub",
+            Self::Mul=>"mul",
+            Self::Div=>"div",
+            Self::Mod=>"mod",
+            Self::Negate=>"neg",
+            Self::Not=>"not",
+            Self::BitAnd=>"bitand",
+            Self::BitOr=>"bitor",
+            Self::BitXor=>"bitxor",
+            Self::Shl=>"shl",
+            Self::Shr=>"shr",
+            Self::BitNot=>"bitnot",
+            Self::Equal=>"eq",
+            Self::NotEqual=>"neq",
+            Self::Less=>"lt",
+            Self::Greater=>"gt",
+            Self::LessEqual=>"le",
+            Self::GreaterEqual=>"ge",
+            Self::In=>"in",
+            Self::Index=>"index",
+            Self::Pop=>"pop",
+            Self::Print=>"print",
+            Self::Concat=>"concat",
+        }
+    }
+
+    /// How many operand bytes follow the opcode
+    pub fn operand_bytes(&self)->usize {
+        match self {
+            Self::Nop|
+                Self::Return|
+                Self::ReturnValue|
+                Self::Add|
+                Self::Sub|
+                Self::Mul|
+                Self::Div|
+                Self::Mod|
+                Self::Negate|
+                Self::Not|
+                Self::BitAnd|
+                Self::BitOr|
+                Self::BitXor|
+                Self::Shl|
+                Self::Shr|
+                Self::BitNot|
+                Self::Equal|
+                Self::NotEqual|
+                Self::Less|
+                Self::Greater|
+                Self::LessEqual|
+                Self::GreaterEqual|
+                Self::In|
+                Self::Index|
+                Self::Pop|
+                Self::Print=>0,
+            Self::Call|
+                Self::Concat|
+                Self::Constant|
+                Self::GetLocal|
+                Self::SetLocal=>1,
+            Self::Constant2|
+                Self::Jump|
+                Self::JumpIfFalse|
+                Self::JumpIfTrue=>2,
+            Self::Constant3=>3,
+        }
+    }
 }
@@ -1,3 +1,9 @@
+use std::io::{
+    Write,
+    Result as IoResult,
+    stdout,
+};
+use test_lang_common::SpanConverter;
 use crate::{
     bytecode::*,
     Module,
@@ -6,58 +12,176 @@ use Instruction as I;
 
 
 pub trait Disassemble {
+    /// Print the disassembly to STDOUT with the raw source spans
     fn disassemble(&self);
+
+    /// Write the disassembly to `w`, showing the `line:col` and source code of each span instead
+    /// of the raw byte range. `source` must be the source code the module was built from.
+    fn disassemble_with_source<W: Write>(&self, source: &str, w: &mut W)->IoResult<()>;
 }
 impl<'a> Disassemble for Module<'a> {
     fn disassemble(&self) {
+        self.disassemble_to(None, &mut stdout().lock()).ok();
+    }
+
+    fn disassemble_with_source<W: Write>(&self, source: &str, w: &mut W)->IoResult<()> {
+        self.disassemble_to(Some(source), w)
+    }
+}
+impl<'a> Module<'a> {
+    fn disassemble_to<W: Write>(&self, source: Option<&str>, w: &mut W)->IoResult<()> {
+        let converter = source.map(SpanConverter::new);
+
         let mut ip = 0;
-        let mut bytecode_span = &self.spans[0];
-        let mut next_bytecode_span = 1;
+        // modules loaded from a file may not have any spans
+        let mut spans = self.spans.iter();
+        let mut bytecode_span = spans.next();
 
         while ip < self.code.len() {
-            let print_bytecode_span = ip >= bytecode_span.instruction_span.end;
-            if print_bytecode_span {
-                bytecode_span = &self.spans[next_bytecode_span];
-                next_bytecode_span += 1;
-                print!("{:<9?}| ", bytecode_span.source_span);
+            let next_span = match bytecode_span {
+                Some(current) if ip >= current.instruction_span.end=>{
+                    bytecode_span = spans.next();
+                    bytecode_span
+                },
+                _=>None,
+            };
+            if let Some(next_span) = next_span {
+                let span = next_span.source_span.clone();
+
+                match (source, &converter) {
+                    (Some(source), Some(converter))=>{
+                        // only show the first line of multi-line spans
+                        let snippet = source[span.clone()]
+                            .lines()
+                            .next()
+                            .unwrap_or("")
+                            .trim();
+                        writeln!(w, "          ; {snippet}")?;
+
+                        let start = converter.convert(span).into_inner().0;
+                        let location = format!("{}:{}", start.line + 1, start.column + 1);
+                        write!(w, "{location:<9}| ")?;
+                    },
+                    _=>write!(w, "{:<9?}| ", span)?,
+                }
             } else {
-                print!("          | ");
+                write!(w, "          | ")?;
             }
 
-            print!("{ip}");
+            write!(w, "{ip:<6}")?;
 
-            let opcode = I::from(self.code[ip]);
+            let opcode = match self.decode_at(ip) {
+                Ok((opcode, _))=>opcode,
+                Err(e)=>{
+                    writeln!(w, "{}", e.err_type())?;
+                    return Ok(());
+                },
+            };
             ip += 1;
 
             match opcode {
-                I::Nop=>println!("nop"),
-                I::Return=>println!("ret"),
-                I::ReturnValue=>{
-                    // TODO: values
-                    println!("retVal");
-                },
-                I::Call=>{
+                I::Nop|I::Return|I::ReturnValue|
+                    I::Add|I::Sub|I::Mul|I::Div|I::Mod|I::Negate|I::Not|
+                    I::BitAnd|I::BitOr|I::BitXor|I::Shl|I::Shr|I::BitNot|
+                    I::Equal|I::NotEqual|I::Less|I::Greater|I::LessEqual|I::GreaterEqual|I::In|
+                    I::Index|I::Pop|I::Print=>{
+                        writeln!(w, "{}", opcode.name())?;
+                    },
+                I::Call|I::Concat=>{
                     let count = self.code[ip];
                     ip += 1;
 
-                    println!("call      {count}");
+                    writeln!(w, "{:<10}{count}", opcode.name())?;
+                },
+                I::GetLocal|I::SetLocal=>{
+                    let slot = self.code[ip];
+                    ip += 1;
+
+                    writeln!(w, "{:<10}{slot}", opcode.name())?;
                 },
                 I::Constant=>{
                     let constant = self.read_const1(&mut ip);
 
-                    println!("const     {constant:?}");
+                    writeln!(w, "const     {constant:?}")?;
                 },
                 I::Constant2=>{
                     let constant = self.read_const2(&mut ip);
 
-                    println!("const     {constant:?}");
+                    writeln!(w, "const     {constant:?}")?;
                 },
                 I::Constant3=>{
                     let constant = self.read_const3(&mut ip);
 
-                    println!("const     {constant:?}");
+                    writeln!(w, "const     {constant:?}")?;
+                },
+                I::Jump|I::JumpIfFalse|I::JumpIfTrue=>{
+                    let target = self.read_jump_target(&mut ip);
+
+                    writeln!(w, "{:<10}{target}", opcode.name())?;
                 },
             }
         }
+
+        return Ok(());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        module_builder::ModuleBuilder,
+        Constant,
+        ModuleId,
+    };
+    use super::*;
+
+    #[test]
+    fn source_locations() {
+        let source = "let x = 1\nprint x + 2\n";
+
+        let mut builder = ModuleBuilder::new(0..0);
+        builder
+            .set_span(8..9)
+            .push_constant(Constant::Integer(1))
+            .set_span(16..21)
+            .push_get_local(0)
+            .push_constant(Constant::Integer(2))
+            .push_add();
+        let module = builder.finish(ModuleId(0), "test");
+
+        let mut out = Vec::new();
+        module.disassemble_with_source(source, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let expected = [
+            "          ; 1",
+            "1:9      | 0     const     Integer(1)",
+            "          ; x + 2",
+            "2:7      | 2     get       0",
+            "          | 4     const     Integer(2)",
+            "          | 6     add",
+        ];
+        assert_eq!(out.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn no_spans() {
+        let module = Module {
+            id: ModuleId(0),
+            name: "test".into(),
+            code: vec![I::Nop.into(), I::Return.into()],
+            constants: Vec::new(),
+            spans: Vec::new(),
+        };
+
+        let mut out = Vec::new();
+        module.disassemble_with_source("", &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(out.lines().collect::<Vec<_>>(), [
+            "          | 0     nop",
+            "          | 1     ret",
+        ]);
     }
 }
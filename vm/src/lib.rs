@@ -1,5 +1,18 @@
-use num_enum::FromPrimitive;
+use num_enum::TryFromPrimitive;
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    hash::{
+        Hash,
+        Hasher,
+    },
+    borrow::Cow,
+};
 use bytecode::*;
+use module_builder::ConstantId;
 use test_lang_common::{
     error::*,
     Span,
@@ -10,9 +23,14 @@ use Instruction as I;
 pub mod bytecode;
 pub mod module_builder;
 pub mod debug;
+pub mod serialize;
+
+
+/// The default maximum depth of the call stack before [`Program::run`] gives up.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Constant {
     Integer(i64),
     Float(f64),
@@ -20,14 +38,77 @@ pub enum Constant {
     String(String),
     ModuleId(usize),
 }
+/// Floats are compared by their bits so identical constants (including `NaN`) are equal, but `0.0`
+/// and `-0.0` are not.
+impl PartialEq for Constant {
+    fn eq(&self, other: &Self)->bool {
+        use Constant::*;
+        match (self, other) {
+            (Integer(a), Integer(b))=>a == b,
+            (Float(a), Float(b))=>a.to_bits() == b.to_bits(),
+            (Bool(a), Bool(b))=>a == b,
+            (String(a), String(b))=>a == b,
+            (ModuleId(a), ModuleId(b))=>a == b,
+            _=>false,
+        }
+    }
+}
+impl Eq for Constant {}
+/// Hashes floats by their bits to match [`PartialEq`]
+impl Hash for Constant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use Constant::*;
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Integer(i)=>i.hash(state),
+            Float(n)=>n.to_bits().hash(state),
+            Bool(b)=>b.hash(state),
+            String(s)=>s.hash(state),
+            ModuleId(id)=>id.hash(state),
+        }
+    }
+}
+
+/// A runtime value on the operand stack
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    ModuleId(ModuleId),
+}
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Integer(i)=>write!(f, "{i}"),
+            Self::Float(n)=>write!(f, "{n}"),
+            Self::Bool(b)=>write!(f, "{b}"),
+            Self::String(s)=>write!(f, "{s}"),
+            Self::ModuleId(id)=>write!(f, "<module {}>", id.0),
+        }
+    }
+}
+impl From<&Constant> for Value {
+    fn from(constant: &Constant)->Self {
+        match constant {
+            Constant::Integer(i)=>Value::Integer(*i),
+            Constant::Float(f)=>Value::Float(*f),
+            Constant::Bool(b)=>Value::Bool(*b),
+            Constant::String(s)=>Value::String(s.clone()),
+            Constant::ModuleId(id)=>Value::ModuleId(ModuleId(*id)),
+        }
+    }
+}
 
 pub enum ModuleReturn {
-    // TODO: values
-    Data,
+    Data(Value),
     Call {
         call_id: ModuleId,
         ip: usize,
-        args: Vec<()>,
+        /// the stack index of the caller's first local, so it can be resumed later
+        base: usize,
+        args: Vec<Value>,
     },
     Done,
 }
@@ -38,10 +119,11 @@ pub enum CallItem {
     Suspended {
         module: ModuleId,
         ip: usize,
+        base: usize,
     },
     Start {
         module: ModuleId,
-        args: Vec<()>,
+        args: Vec<Value>,
     },
 }
 impl CallItem {
@@ -53,28 +135,32 @@ impl CallItem {
         }
     }
 
-    pub fn suspend(&mut self, new_ip: usize) {
+    pub fn suspend(&mut self, new_ip: usize, new_base: usize) {
         match self {
-            Self::Current(module)=>*self = Self::Suspended {module: *module, ip: new_ip},
-            Self::Suspended{ip,..}=>*ip = new_ip,
-            Self::Start{module,..}=>*self = Self::Suspended {module: *module, ip: new_ip},
+            Self::Current(module)=>*self = Self::Suspended {module: *module, ip: new_ip, base: new_base},
+            Self::Suspended{ip,base,..}=>{
+                *ip = new_ip;
+                *base = new_base;
+            },
+            Self::Start{module,..}=>*self = Self::Suspended {module: *module, ip: new_ip, base: new_base},
         }
     }
 
-    pub fn resume(&mut self)->usize {
+    /// Returns the `ip` and stack base to resume at
+    pub fn resume(&mut self)->(usize, usize) {
         match self {
-            Self::Current(_)=>0,
-            Self::Suspended{ip,module}=>{
-                let ip = *ip;
+            Self::Current(_)=>(0, 0),
+            Self::Suspended{ip,module,base}=>{
+                let ret = (*ip, *base);
                 *self = Self::Current(*module);
 
-                return ip;
+                return ret;
             },
-            Self::Start{..}=>0,
+            Self::Start{..}=>(0, 0),
         }
     }
 
-    pub fn args(&mut self)->Option<Vec<()>> {
+    pub fn args(&mut self)->Option<Vec<Value>> {
         match self {
             Self::Start{module,..}=>{
                 let new_self = Self::Current(*module);
@@ -92,8 +178,28 @@ impl CallItem {
 pub struct Program<'a> {
     modules: Vec<Module<'a>>,
     global_module: ModuleId,
+    max_call_depth: usize,
+    /// the operand stack shared by all modules
+    stack: Vec<Value>,
 }
 impl<'a> Program<'a> {
+    pub fn new(modules: Vec<Module<'a>>, global_module: ModuleId)->Self {
+        Program {
+            modules,
+            global_module,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Set the maximum number of modules that can be on the call stack at once. Going over this
+    /// returns a `CallStackOverflow` error instead of growing the stack forever.
+    pub fn set_max_call_depth(&mut self, depth: usize)->&mut Self {
+        self.max_call_depth = depth;
+
+        return self;
+    }
+
     pub fn run(&mut self)->Result<(), Error> {
         let mut call_stack = vec![CallItem::Current(self.global_module)];
         while let Some(mut item) = call_stack.pop()  {
@@ -101,24 +207,36 @@ impl<'a> Program<'a> {
             if let Some(args) = item.args() {
                 ret = self
                     .modules[item.mod_id().0]
-                    .start(args)?;
+                    .start(args, &mut self.stack)?;
             } else {
+                let (ip, base) = item.resume();
                 ret = self
                     .modules[item.mod_id().0]
-                    .run(item.resume())?;
+                    .run(ip, base, &mut self.stack)?;
             }
 
             match ret {
                 // We have already done everything required to exit the scope
                 ModuleReturn::Done=>{},
                 // Suspend the current function and push the next one
-                ModuleReturn::Call{call_id,ip,args}=>{
-                    item.suspend(ip);
+                ModuleReturn::Call{call_id,ip,base,args}=>{
+                    // the current item was popped off the stack, and the new call adds one more
+                    if call_stack.len() + 1 >= self.max_call_depth {
+                        let span = self
+                            .modules[item.mod_id().0]
+                            .span_at(ip.saturating_sub(1))
+                            .unwrap_or(0..0);
+
+                        return Err(Error::new(span, ErrorType::CallStackOverflow(self.max_call_depth)));
+                    }
+
+                    item.suspend(ip, base);
                     call_stack.push(item);
                     call_stack.push(CallItem::Start{module:call_id,args});
                 },
-                // TODO: function returns
-                ModuleReturn::Data=>todo!(),
+                // The caller is suspended on top of the call stack, so push the value onto the
+                // operand stack and let it pick up the value when it resumes on the next loop.
+                ModuleReturn::Data(value)=>self.stack.push(value),
             }
         }
         return Ok(());
@@ -127,74 +245,363 @@ impl<'a> Program<'a> {
 
 pub struct Module<'a> {
     id: ModuleId,
-    name: &'a str,
+    /// borrowed when built from source, and owned when loaded from a file
+    name: Cow<'a, str>,
     code: Vec<u8>,
     constants: Vec<Constant>,
     spans: Vec<BytecodeSpan>,
 }
 impl<'a> Module<'a> {
-    pub fn start(&self, _args: Vec<()>)->Result<ModuleReturn, Error> {
-        // TODO: arguments
-        return self.run(0);
+    /// Start the module from the beginning. The arguments are pushed onto the stack in order and
+    /// become the first local slots.
+    pub fn start(&self, args: Vec<Value>, stack: &mut Vec<Value>)->Result<ModuleReturn, Error> {
+        let base = stack.len();
+        stack.extend(args);
+        return self.run(0, base, stack);
     }
-    /// Run with an optional `ip` parameter used to resume the module
-    pub fn run(&self, mut ip: usize)->Result<ModuleReturn, Error> {
+    /// Run with an optional `ip` parameter used to resume the module. `base` is the stack index of
+    /// local slot 0.
+    pub fn run(&self, mut ip: usize, base: usize, stack: &mut Vec<Value>)->Result<ModuleReturn, Error> {
 
         while ip < self.code.len() {
-            let ins_byte = self.code[ip];
+            let (ins, _) = self.decode_at(ip)?;
+            let ins_ip = ip;
             ip += 1;
 
-            match Instruction::from_primitive(ins_byte) {
+            match ins {
                 I::Nop=>{},
+                // returning drops the locals and anything else left on the stack by this module
                 I::Return=>{
-                    todo!();
+                    stack.truncate(base);
+
+                    return Ok(ModuleReturn::Done);
                 },
                 I::ReturnValue=>{
-                    todo!();
-                },
-                I::Call=>{
-                    todo!();
+                    let value = self.pop(ins_ip, stack)?;
+                    stack.truncate(base);
+
+                    return Ok(ModuleReturn::Data(value));
                 },
-                I::Constant=>{
-                    let num = self.code[ip];
+                I::GetLocal=>{
+                    let slot = self.code[ip];
                     ip += 1;
 
-                    let constant = &self.constants[num as usize];
+                    let Some(value) = stack.get(base + slot as usize) else {
+                        return Err(self.error_at(ins_ip, ErrorType::InvalidLocal(slot)));
+                    };
 
-                    println!("Constant: {constant:?}");
+                    stack.push(value.clone());
                 },
-                I::Constant2=>{
-                    let num = self.code[ip];
+                // setting the slot just past the top of the stack creates the local
+                I::SetLocal=>{
+                    let slot = self.code[ip];
                     ip += 1;
-                    let num1 = self.code[ip];
+
+                    let value = self.pop(ins_ip, stack)?;
+                    let index = base + slot as usize;
+
+                    if index < stack.len() {
+                        stack[index] = value;
+                    } else if index == stack.len() {
+                        stack.push(value);
+                    } else {
+                        return Err(self.error_at(ins_ip, ErrorType::InvalidLocal(slot)));
+                    }
+                },
+                // the callee is pushed before the arguments
+                I::Call=>{
+                    let arg_count = self.code[ip] as usize;
                     ip += 1;
 
-                    let num = u16::from_le_bytes([num,num1]);
+                    if stack.len() < arg_count {
+                        return Err(self.error_at(ins_ip, ErrorType::StackUnderflow));
+                    }
+                    let args = stack.split_off(stack.len() - arg_count);
+
+                    let Value::ModuleId(call_id) = self.pop(ins_ip, stack)? else {
+                        return Err(self.error_at(ins_ip, ErrorType::CannotCall));
+                    };
+
+                    return Ok(ModuleReturn::Call {
+                        call_id,
+                        ip,
+                        base,
+                        args,
+                    });
+                },
+                I::Constant=>{
+                    let constant = self.read_const1(&mut ip);
 
-                    let constant = &self.constants[num as usize];
+                    stack.push(constant.into());
+                },
+                I::Constant2=>{
+                    let constant = self.read_const2(&mut ip);
 
-                    println!("Constant: {constant:?}");
+                    stack.push(constant.into());
                 },
                 I::Constant3=>{
-                    let num = self.code[ip];
-                    ip += 1;
-                    let num1 = self.code[ip];
-                    ip += 1;
-                    let num2 = self.code[ip];
-                    ip += 1;
+                    let constant = self.read_const3(&mut ip);
 
-                    let num = u32::from_le_bytes([num,num1,num2,0]);
+                    stack.push(constant.into());
+                },
+                I::Jump=>ip = self.checked_jump_target(ins_ip, &mut ip)?,
+                I::JumpIfFalse|I::JumpIfTrue=>{
+                    let target = self.checked_jump_target(ins_ip, &mut ip)?;
 
-                    let constant = &self.constants[num as usize];
+                    let Value::Bool(condition) = self.pop(ins_ip, stack)? else {
+                        return Err(self.error_at(ins_ip, ErrorType::InvalidType));
+                    };
 
-                    println!("Constant: {constant:?}");
+                    if condition == matches!(ins, I::JumpIfTrue) {
+                        ip = target;
+                    }
                 },
+                I::Negate=>{
+                    let value = match self.pop(ins_ip, stack)? {
+                        Value::Integer(i)=>Value::Integer(i
+                            .checked_neg()
+                            .ok_or_else(||self.error_at(ins_ip, ErrorType::IntegerOverflow))?
+                        ),
+                        Value::Float(f)=>Value::Float(-f),
+                        _=>return Err(Error::unary(self.span_at(ins_ip).unwrap_or(0..0), ins.name().to_string())),
+                    };
+
+                    stack.push(value);
+                },
+                I::Not=>{
+                    let Value::Bool(b) = self.pop(ins_ip, stack)? else {
+                        return Err(Error::unary(self.span_at(ins_ip).unwrap_or(0..0), ins.name().to_string()));
+                    };
+
+                    stack.push(Value::Bool(!b));
+                },
+                I::BitNot=>{
+                    let Value::Integer(i) = self.pop(ins_ip, stack)? else {
+                        return Err(Error::unary(self.span_at(ins_ip).unwrap_or(0..0), ins.name().to_string()));
+                    };
+
+                    stack.push(Value::Integer(!i));
+                },
+                I::Index=>{
+                    let index = self.pop(ins_ip, stack)?;
+                    let value = self.pop(ins_ip, stack)?;
+
+                    stack.push(self.index(ins_ip, value, index)?);
+                },
+                I::Pop=>{
+                    self.pop(ins_ip, stack)?;
+                },
+                I::Print=>{
+                    let value = self.pop(ins_ip, stack)?;
+
+                    print!("{value}");
+                },
+                I::Concat=>{
+                    let count = self.code[ip] as usize;
+                    ip += 1;
+
+                    if stack.len() < count {
+                        return Err(self.error_at(ins_ip, ErrorType::StackUnderflow));
+                    }
+                    let out = stack
+                        .drain(stack.len() - count..)
+                        .map(|value|value.to_string())
+                        .collect();
+
+                    stack.push(Value::String(out));
+                },
+                I::Add|I::Sub|I::Mul|I::Div|I::Mod|
+                    I::BitAnd|I::BitOr|I::BitXor|I::Shl|I::Shr|
+                    I::Equal|I::NotEqual|I::Less|I::Greater|I::LessEqual|I::GreaterEqual|I::In=>{
+                        let right = self.pop(ins_ip, stack)?;
+                        let left = self.pop(ins_ip, stack)?;
+
+                        stack.push(self.binary_op(ins_ip, ins, left, right)?);
+                    },
             }
         }
 
         return Ok(ModuleReturn::Done);
     }
 
+    /// Index a string by characters, not bytes. Negative indices can't index anything.
+    fn index(&self, ip: usize, value: Value, index: Value)->Result<Value, Error> {
+        let Value::String(s) = value else {
+            return Err(self.error_at(ip, ErrorType::CannotIndex));
+        };
+        let Value::Integer(i) = index else {
+            return Err(self.error_at(ip, ErrorType::InvalidIndexType));
+        };
+        if i < 0 {
+            return Err(self.error_at(ip, ErrorType::CannotIndex));
+        }
+
+        s.chars()
+            .nth(i as usize)
+            .map(|c|Value::String(c.to_string()))
+            .ok_or_else(||self.error_at(ip, ErrorType::ArrayOutOfBounds))
+    }
+
+    /// Apply a binary instruction. Integers are promoted to floats when the other side is a float.
+    fn binary_op(&self, ip: usize, ins: Instruction, left: Value, right: Value)->Result<Value, Error> {
+        use Value as V;
+
+        let unsupported = ||Error::binary(self.span_at(ip).unwrap_or(0..0), ins.name().to_string());
+
+        let (left, right) = match (left, right) {
+            (V::Integer(l), V::Float(r))=>(V::Float(l as f64), V::Float(r)),
+            (V::Float(l), V::Integer(r))=>(V::Float(l), V::Float(r as f64)),
+            sides=>sides,
+        };
+
+        let value = match (ins, left, right) {
+            (I::Equal, l, r)=>V::Bool(l == r),
+            (I::NotEqual, l, r)=>V::Bool(l != r),
+
+            (I::Add, V::Integer(l), V::Integer(r))=>V::Integer(l
+                .checked_add(r)
+                .ok_or_else(||self.error_at(ip, ErrorType::IntegerOverflow))?
+            ),
+            (I::Sub, V::Integer(l), V::Integer(r))=>V::Integer(l
+                .checked_sub(r)
+                .ok_or_else(||self.error_at(ip, ErrorType::IntegerOverflow))?
+            ),
+            (I::Mul, V::Integer(l), V::Integer(r))=>V::Integer(l
+                .checked_mul(r)
+                .ok_or_else(||self.error_at(ip, ErrorType::IntegerOverflow))?
+            ),
+            (I::Div|I::Mod, V::Integer(_), V::Integer(0))=>return Err(self.error_at(ip, ErrorType::DivideByZero)),
+            // the only overflow left is `i64::MIN / -1`
+            (I::Div, V::Integer(l), V::Integer(r))=>V::Integer(l
+                .checked_div(r)
+                .ok_or_else(||self.error_at(ip, ErrorType::IntegerOverflow))?
+            ),
+            (I::Mod, V::Integer(l), V::Integer(r))=>V::Integer(l
+                .checked_rem(r)
+                .ok_or_else(||self.error_at(ip, ErrorType::IntegerOverflow))?
+            ),
+            (I::BitAnd, V::Integer(l), V::Integer(r))=>V::Integer(l & r),
+            (I::BitOr, V::Integer(l), V::Integer(r))=>V::Integer(l | r),
+            (I::BitXor, V::Integer(l), V::Integer(r))=>V::Integer(l ^ r),
+            (I::Shl|I::Shr, V::Integer(_), V::Integer(r)) if !(0..64).contains(&r)=>{
+                return Err(self.error_at(ip, ErrorType::InvalidShift(r)));
+            },
+            (I::Shl, V::Integer(l), V::Integer(r))=>V::Integer(l << r),
+            (I::Shr, V::Integer(l), V::Integer(r))=>V::Integer(l >> r),
+            (I::Less, V::Integer(l), V::Integer(r))=>V::Bool(l < r),
+            (I::Greater, V::Integer(l), V::Integer(r))=>V::Bool(l > r),
+            (I::LessEqual, V::Integer(l), V::Integer(r))=>V::Bool(l <= r),
+            (I::GreaterEqual, V::Integer(l), V::Integer(r))=>V::Bool(l >= r),
+
+            // float division by zero follows IEEE 754
+            (I::Add, V::Float(l), V::Float(r))=>V::Float(l + r),
+            (I::Sub, V::Float(l), V::Float(r))=>V::Float(l - r),
+            (I::Mul, V::Float(l), V::Float(r))=>V::Float(l * r),
+            (I::Div, V::Float(l), V::Float(r))=>V::Float(l / r),
+            (I::Mod, V::Float(l), V::Float(r))=>V::Float(l % r),
+            (I::Less, V::Float(l), V::Float(r))=>V::Bool(l < r),
+            (I::Greater, V::Float(l), V::Float(r))=>V::Bool(l > r),
+            (I::LessEqual, V::Float(l), V::Float(r))=>V::Bool(l <= r),
+            (I::GreaterEqual, V::Float(l), V::Float(r))=>V::Bool(l >= r),
+
+            (I::Add, V::String(l), V::String(r))=>V::String(l + &r),
+            (I::Less, V::String(l), V::String(r))=>V::Bool(l < r),
+            (I::Greater, V::String(l), V::String(r))=>V::Bool(l > r),
+            (I::LessEqual, V::String(l), V::String(r))=>V::Bool(l <= r),
+            (I::GreaterEqual, V::String(l), V::String(r))=>V::Bool(l >= r),
+            (I::In, V::String(l), V::String(r))=>V::Bool(r.contains(&l)),
+            (I::In, ..)=>return Err(self.error_at(ip, ErrorType::InvalidType)),
+
+            _=>return Err(unsupported()),
+        };
+
+        return Ok(value);
+    }
+
+    /// Read a jump target and make sure it is inside the code. Jumping to the end of the code is
+    /// allowed and finishes the module.
+    fn checked_jump_target(&self, ins_ip: usize, ip: &mut usize)->Result<usize, Error> {
+        let target = self.read_jump_target(ip);
+        if target > self.code.len() {
+            return Err(self.error_at(ins_ip, ErrorType::JumpOutOfBounds(target)));
+        }
+
+        return Ok(target);
+    }
+
+    fn error_at(&self, ip: usize, err_type: ErrorType)->Error {
+        Error::new(self.span_at(ip).unwrap_or(0..0), err_type)
+    }
+
+    /// Pop a value off the stack, or return a `StackUnderflow` error for the instruction at `ip`
+    fn pop(&self, ip: usize, stack: &mut Vec<Value>)->Result<Value, Error> {
+        stack
+            .pop()
+            .ok_or_else(||self.error_at(ip, ErrorType::StackUnderflow))
+    }
+
+    /// Decode the instruction at `ip` and return it along with how many operand bytes follow it.
+    /// Returns an error if the opcode is invalid or the operands run past the end of the code.
+    pub fn decode_at(&self, ip: usize)->Result<(Instruction, usize), Error> {
+        let span = self.span_at(ip).unwrap_or(0..0);
+
+        let Some(&byte) = self.code.get(ip) else {
+            return Err(Error::eof(span));
+        };
+
+        let ins = Instruction::try_from_primitive(byte)
+            .map_err(|_|Error::new(span.clone(), ErrorType::InvalidOpcode(byte)))?;
+        let operand_bytes = ins.operand_bytes();
+
+        if ip + operand_bytes >= self.code.len() {
+            return Err(Error::eof(span));
+        }
+
+        return Ok((ins, operand_bytes));
+    }
+
+    /// The raw bytecode of this module
+    pub fn code(&self)->&[u8] {
+        &self.code
+    }
+
+    /// The constant pool of this module
+    pub fn constants(&self)->&[Constant] {
+        &self.constants
+    }
+
+    /// The spans mapping the bytecode back to the source code
+    pub fn spans(&self)->&[BytecodeSpan] {
+        &self.spans
+    }
+
+    /// Get the constant referenced by `id`. Panics if it is not in this module's constant pool.
+    pub fn constant_at(&self, id: ConstantId)->&Constant {
+        let index = match id {
+            ConstantId::One(i)=>i as usize,
+            ConstantId::Two(i)=>i as usize,
+            ConstantId::Three(i)=>i as usize,
+        };
+
+        &self.constants[index]
+    }
+
+    /// Get the source span of the instruction at `ip`, if there is one
+    pub fn span_at(&self, ip: usize)->Option<Span> {
+        self.spans
+            .iter()
+            .find_map(|span|span.try_get_span(ip))
+    }
+
+    /// Read a jump offset and return the absolute target it points to. A target before the start
+    /// of the code wraps around to a huge value, so it is caught by bounds checks on the end.
+    pub fn read_jump_target(&self, ip: &mut usize)->usize {
+        let offset = i16::from_le_bytes([self.code[*ip], self.code[*ip + 1]]);
+        *ip += 2;
+
+        (*ip as isize + offset as isize) as usize
+    }
+
     pub fn read_const1(&self, ip: &mut usize)->&Constant {
         let num = self.code[*ip];
         *ip += 1;
@@ -256,3 +663,257 @@ impl BytecodeSpan {
 #[repr(transparent)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub struct ModuleId(pub usize);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(code: Vec<u8>)->Module<'static> {
+        Module {
+            id: ModuleId(0),
+            name: Cow::Borrowed("test"),
+            code,
+            constants: Vec::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// run `op` on two constants and return the result
+    fn binary_op(
+        left: Constant,
+        right: Constant,
+        op: fn(&mut module_builder::ModuleBuilder)->&mut module_builder::ModuleBuilder,
+    )->Result<Value, Error> {
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder
+            .push_constant(left)
+            .push_constant(right);
+        op(&mut builder);
+
+        let mut program = Program::new(vec![builder.finish(ModuleId(0), "test")], ModuleId(0));
+        program.run()?;
+
+        return Ok(program.stack.pop().unwrap());
+    }
+
+    #[test]
+    fn call_stack_overflow() {
+        // a module that calls itself forever
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder
+            .push_constant(Constant::ModuleId(0))
+            .push_call(0)
+            .push_ret();
+        let mut program = Program::new(vec![builder.finish(ModuleId(0), "test")], ModuleId(0));
+        program.set_max_call_depth(10);
+
+        let err = program.run().unwrap_err();
+        assert_eq!(err.err_type(), &ErrorType::CallStackOverflow(10));
+    }
+
+    #[test]
+    fn return_value_goes_to_the_caller() {
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder
+            .push_constant(Constant::ModuleId(1))
+            .push_call(0)
+            .push_constant(Constant::Integer(1))
+            .push_add()
+            .push_ret_val();
+        let main = builder.finish(ModuleId(0), "main");
+
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder
+            .push_constant(Constant::Integer(41))
+            .push_ret_val();
+        let function = builder.finish(ModuleId(1), "function");
+
+        let mut program = Program::new(vec![main, function], ModuleId(0));
+        program.run().unwrap();
+
+        // the main module's return value is left on the stack
+        assert_eq!(program.stack, [Value::Integer(42)]);
+    }
+
+    #[test]
+    fn two_module_program() {
+        // `let answer = double(21)`, where `double` is module 1
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder
+            .push_constant(Constant::ModuleId(1))
+            .push_constant(Constant::Integer(21))
+            .push_call(1)
+            .push_set_local(0);
+        let main = builder.finish(ModuleId(0), "main");
+
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder
+            .push_get_local(0)
+            .push_get_local(0)
+            .push_add()
+            .push_ret_val();
+        let double = builder.finish(ModuleId(1), "double");
+
+        let mut program = Program::new(vec![main, double], ModuleId(0));
+        program.run().unwrap();
+
+        // the argument is dropped when `double` returns, leaving only the global
+        assert_eq!(program.stack, [Value::Integer(42)]);
+    }
+
+    #[test]
+    fn jumps() {
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder
+            .push_constant(Constant::Integer(3))
+            .push_set_local(0)
+            .push_constant(Constant::Integer(0))
+            .push_set_local(1);
+
+        // add 3, 2, and 1 to local 1
+        let start = builder.current_offset();
+        builder
+            .push_get_local(0)
+            .push_constant(Constant::Integer(0))
+            .push_greater();
+        let exit = builder.push_jump_if_false();
+        builder
+            .push_get_local(1)
+            .push_get_local(0)
+            .push_add()
+            .push_set_local(1)
+            .push_get_local(0)
+            .push_constant(Constant::Integer(1))
+            .push_sub()
+            .push_set_local(0)
+            .emit_jump_to(start)
+            .unwrap()
+            .patch_jump(exit)
+            .unwrap();
+
+        // both of these skip overwriting local 1
+        let skip = builder.push_jump();
+        builder
+            .push_constant(Constant::Integer(100))
+            .push_set_local(1)
+            .patch_jump(skip)
+            .unwrap()
+            .push_constant(Constant::Bool(true));
+        let skip = builder.push_jump_if_true();
+        builder
+            .push_constant(Constant::Integer(1000))
+            .push_set_local(1)
+            .patch_jump(skip)
+            .unwrap();
+
+        let mut program = Program::new(vec![builder.finish(ModuleId(0), "test")], ModuleId(0));
+        program.run().unwrap();
+
+        assert_eq!(program.stack, [Value::Integer(0), Value::Integer(6)]);
+    }
+
+    #[test]
+    fn jump_out_of_bounds() {
+        let run = |code|Program::new(vec![module(code)], ModuleId(0)).run();
+
+        let err = run(vec![I::Jump.into(), 10, 0]).unwrap_err();
+        assert_eq!(err.err_type(), &ErrorType::JumpOutOfBounds(13));
+
+        let err = run(vec![I::Nop.into(), I::JumpIfFalse.into(), 1, 0]).unwrap_err();
+        assert_eq!(err.err_type(), &ErrorType::JumpOutOfBounds(5));
+
+        // jumping before the start wraps around to a huge target
+        let err = run(vec![I::Jump.into(), 0xf0, 0xff]).unwrap_err();
+        assert!(matches!(err.err_type(), ErrorType::JumpOutOfBounds(_)));
+
+        // jumping to the end of the code just stops
+        run(vec![I::Jump.into(), 0, 0]).unwrap();
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        use module_builder::ModuleBuilder as B;
+        let int = |l, r, op|binary_op(Constant::Integer(l), Constant::Integer(r), op);
+
+        assert_eq!(int(0b1100, 0b1010, B::push_bit_and).unwrap(), Value::Integer(0b1000));
+        assert_eq!(int(0b1100, 0b1010, B::push_bit_or).unwrap(), Value::Integer(0b1110));
+        assert_eq!(int(0b1100, 0b1010, B::push_bit_xor).unwrap(), Value::Integer(0b0110));
+        assert_eq!(int(1, 63, B::push_shl).unwrap(), Value::Integer(i64::MIN));
+        assert_eq!(int(-8, 1, B::push_shr).unwrap(), Value::Integer(-4));
+
+        // `~` only takes the top value
+        assert_eq!(int(0, 5, B::push_bit_not).unwrap(), Value::Integer(!5));
+
+        assert_eq!(int(1, 64, B::push_shl).unwrap_err().err_type(), &ErrorType::InvalidShift(64));
+        assert_eq!(int(1, -1, B::push_shr).unwrap_err().err_type(), &ErrorType::InvalidShift(-1));
+        let err = binary_op(Constant::Integer(1), Constant::Bool(true), B::push_bit_and).unwrap_err();
+        assert_eq!(err.err_type(), &ErrorType::BinaryOperationNotSupported("bitand".into()));
+    }
+
+    #[test]
+    fn string_membership() {
+        let string = |s: &str|Constant::String(s.into());
+        let push_in = module_builder::ModuleBuilder::push_in;
+
+        assert_eq!(binary_op(string("lo"), string("hello"), push_in).unwrap(), Value::Bool(true));
+        assert_eq!(binary_op(string(""), string("hello"), push_in).unwrap(), Value::Bool(true));
+        assert_eq!(binary_op(string("ol"), string("hello"), push_in).unwrap(), Value::Bool(false));
+
+        let err = binary_op(Constant::Integer(1), string("abc"), push_in).unwrap_err();
+        assert_eq!(err.err_type(), &ErrorType::InvalidType);
+        let err = binary_op(string("a"), Constant::Integer(1), push_in).unwrap_err();
+        assert_eq!(err.err_type(), &ErrorType::InvalidType);
+    }
+
+    #[test]
+    fn decode_truncated_operands() {
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder
+            .push_const(ConstantId::Three(0))
+            .push_get_local(0)
+            .push_call(1);
+        let code = builder.finish(ModuleId(0), "test").code;
+        let boundaries = [0, 4, 6, 8];
+
+        // cutting any operand off must be an error, not a read past the end
+        for len in 0..code.len() {
+            let module = module(code[..len].to_vec());
+            let mut ip = 0;
+            while let Ok((_, operands)) = module.decode_at(ip) {
+                ip += operands + 1;
+                assert!(ip <= len);
+            }
+
+            if !boundaries.contains(&len) {
+                assert!(ip < len);
+            }
+        }
+        assert!(module(vec![I::Constant3.into(), 0, 0]).decode_at(0).is_err());
+        assert!(module(vec![I::Jump.into(), 0]).decode_at(0).is_err());
+    }
+
+    #[test]
+    fn decode_random_bytes() {
+        // a small xorshift generator so the test is the same every run
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random_byte = ||{
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        };
+
+        for len in 0..64 {
+            let code = (0..len).map(|_|random_byte()).collect::<Vec<_>>();
+            let module = module(code);
+
+            // every offset is decoded, including the ones in the middle of an instruction
+            for ip in 0..=len {
+                if let Ok((_, operands)) = module.decode_at(ip) {
+                    assert!(ip + operands < len);
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,9 @@
-use num_enum::{IntoPrimitive,FromPrimitive};
+use fnv::FnvHashMap;
 use test_lang_common::{
     error::*,
     Span,
 };
-use super::{
-    bytecode::*,
-    *,
-};
+use super::*;
 use ConstantId as CID;
 use Instruction as I;
 
@@ -14,6 +11,15 @@ use Instruction as I;
 pub const U24_MAX: usize = 0xffffff;
 
 
+/// A forward jump that still needs its target. Pass it to [`ModuleBuilder::patch_jump`] once the
+/// target is known.
+#[must_use]
+pub struct JumpPatch {
+    /// the offset of the jump's operand bytes
+    operand: usize,
+}
+
+
 pub enum ConstantId {
     One(u8),
     Two(u16),
@@ -27,6 +33,8 @@ pub enum ConstantId {
 pub struct ModuleBuilder {
     code: Vec<u8>,
     constants: Vec<Constant>,
+    /// the index of each constant in `constants`, so equal constants are only stored once
+    constant_indices: FnvHashMap<Constant, usize>,
     /// a list of spans indexing both the bytecode and source code
     spans: Vec<BytecodeSpan>,
     /// the span that indexes the source code
@@ -39,6 +47,7 @@ impl ModuleBuilder {
         ModuleBuilder {
             code: Vec::new(),
             constants: Vec::new(),
+            constant_indices: FnvHashMap::default(),
             spans: Vec::new(),
             current_source_span: start_span,
             current_code_span_start: 0,
@@ -74,24 +83,35 @@ impl ModuleBuilder {
         return self;
     }
 
+    /// Add a constant to the pool and return its id. If an equal constant is already in the pool,
+    /// then its id is returned instead.
     pub fn register_constant(&mut self, constant: Constant)->ConstantId {
-        let const_count = self.constants.len();
-
-        let id = if const_count <= (u8::MAX as usize) {
-            ConstantId::One(const_count as u8)
-        } else if const_count < (u16::MAX as usize) {
-            ConstantId::Two(const_count as u16)
-        } else if const_count < U24_MAX {
-            ConstantId::Three(const_count as u32)
-        } else {
-            panic!("Maximum of {U24_MAX} constants reached!");
-        };
+        if let Some(index) = self.constant_indices.get(&constant) {
+            return Self::constant_id(*index);
+        }
+
+        let index = self.constants.len();
+        let id = Self::constant_id(index);
 
+        self.constant_indices.insert(constant.clone(), index);
         self.constants.push(constant);
 
         return id;
     }
 
+    fn constant_id(index: usize)->ConstantId {
+        // every branch is inclusive so each id type is used up to its maximum value
+        if index <= (u8::MAX as usize) {
+            ConstantId::One(index as u8)
+        } else if index <= (u16::MAX as usize) {
+            ConstantId::Two(index as u16)
+        } else if index <= U24_MAX {
+            ConstantId::Three(index as u32)
+        } else {
+            panic!("Maximum of {U24_MAX} constants reached!");
+        }
+    }
+
     pub fn push_const(&mut self, id: ConstantId)->&mut Self {
         match id {
             CID::One(n)=>{
@@ -104,25 +124,51 @@ impl ModuleBuilder {
             },
             CID::Three(n)=>{
                 self.ins(I::Constant3);
-                self.bytes(n.to_le_bytes());
+                // only write the lower 3 bytes since this is a u24
+                let [b0, b1, b2, _] = n.to_le_bytes();
+                self.bytes([b0, b1, b2]);
             },
         }
 
         return self;
     }
 
+    /// Register `constant` and push it in one step. Use [`Self::register_constant`] and
+    /// [`Self::push_const`] to push the same id multiple times.
+    pub fn push_constant(&mut self, constant: Constant)->&mut Self {
+        let id = self.register_constant(constant);
+        self.push_const(id)
+    }
+
     pub fn push_ret(&mut self)->&mut Self {
         self.ins(I::Return);
         return self;
     }
 
-    pub fn push_ret_val(&mut self, val: ())->&mut Self {
+    /// Return the value on top of the stack
+    pub fn push_ret_val(&mut self)->&mut Self {
         self.ins(I::ReturnValue);
-        // TODO: return value
 
         return self;
     }
 
+    /// Push a copy of the local in `slot`
+    pub fn push_get_local(&mut self, slot: u8)->&mut Self {
+        self.ins(I::GetLocal);
+        self.byte(slot);
+
+        return self;
+    }
+
+    /// Pop a value into the local in `slot`
+    pub fn push_set_local(&mut self, slot: u8)->&mut Self {
+        self.ins(I::SetLocal);
+        self.byte(slot);
+
+        return self;
+    }
+
+    /// Call the module id below the `arg_count` arguments on the stack
     pub fn push_call(&mut self, arg_count: u8)->&mut Self {
         self.ins(I::Call);
         self.byte(arg_count);
@@ -130,9 +176,357 @@ impl ModuleBuilder {
         return self;
     }
 
+    /// Join the top `count` values on the stack into one string
+    pub fn push_concat(&mut self, count: u8)->&mut Self {
+        self.ins(I::Concat);
+        self.byte(count);
+
+        return self;
+    }
+
+    /// The offset the next instruction will be written at. Use this to record jump targets.
+    pub fn current_offset(&self)->usize {
+        self.code.len()
+    }
+
+    /// Jump to an offset previously recorded with [`Self::current_offset`]. Mostly used for
+    /// jumping back to the start of a loop. Errors if the target is too far away for an `i16`
+    /// offset.
+    pub fn emit_jump_to(&mut self, target: usize)->Result<&mut Self, Error> {
+        // the offset is relative to the end of the jump instruction
+        let end = self.code.len() + 3;
+        let offset = target as isize - end as isize;
+
+        let Ok(offset) = i16::try_from(offset) else {
+            return Err(self.jump_too_long());
+        };
+
+        self.ins(I::Jump);
+        self.bytes(offset.to_le_bytes());
+
+        return Ok(self);
+    }
+
+    fn jump_too_long(&self)->Error {
+        Error::new(
+            self.current_source_span.clone(),
+            ErrorType::CompileUnsupported(format!("jumps over more than {} bytes", i16::MAX)),
+        )
+    }
+
+    /// Push a jump with a placeholder target
+    pub fn push_jump(&mut self)->JumpPatch {
+        self.push_patchable_jump(I::Jump)
+    }
+
+    /// Push a jump that pops a bool and jumps if it is false
+    pub fn push_jump_if_false(&mut self)->JumpPatch {
+        self.push_patchable_jump(I::JumpIfFalse)
+    }
+
+    /// Push a jump that pops a bool and jumps if it is true
+    pub fn push_jump_if_true(&mut self)->JumpPatch {
+        self.push_patchable_jump(I::JumpIfTrue)
+    }
+
+    fn push_patchable_jump(&mut self, ins: Instruction)->JumpPatch {
+        self.ins(ins);
+        let operand = self.code.len();
+        self.bytes([0, 0]);
+
+        return JumpPatch {operand};
+    }
+
+    /// Point a jump pushed with one of the `push_jump*` methods at the current offset. Errors if
+    /// the jump is too long for an `i16` offset.
+    pub fn patch_jump(&mut self, patch: JumpPatch)->Result<&mut Self, Error> {
+        // the offset is relative to the end of the jump instruction
+        let end = patch.operand + 2;
+        let offset = self.code.len() - end;
+
+        let Ok(offset) = i16::try_from(offset) else {
+            return Err(self.jump_too_long());
+        };
+
+        self.code[patch.operand..end].copy_from_slice(&offset.to_le_bytes());
+
+        return Ok(self);
+    }
+
     pub fn push_nop(&mut self)->&mut Self {
         self.ins(I::Nop);
 
         return self;
     }
+
+    pub fn push_pop(&mut self)->&mut Self {
+        self.ins(I::Pop);
+
+        return self;
+    }
+
+    pub fn push_print(&mut self)->&mut Self {
+        self.ins(I::Print);
+
+        return self;
+    }
+
+    pub fn push_add(&mut self)->&mut Self {
+        self.ins(I::Add);
+
+        return self;
+    }
+
+    pub fn push_sub(&mut self)->&mut Self {
+        self.ins(I::Sub);
+
+        return self;
+    }
+
+    pub fn push_mul(&mut self)->&mut Self {
+        self.ins(I::Mul);
+
+        return self;
+    }
+
+    pub fn push_div(&mut self)->&mut Self {
+        self.ins(I::Div);
+
+        return self;
+    }
+
+    pub fn push_mod(&mut self)->&mut Self {
+        self.ins(I::Mod);
+
+        return self;
+    }
+
+    pub fn push_negate(&mut self)->&mut Self {
+        self.ins(I::Negate);
+
+        return self;
+    }
+
+    pub fn push_not(&mut self)->&mut Self {
+        self.ins(I::Not);
+
+        return self;
+    }
+
+    pub fn push_bit_and(&mut self)->&mut Self {
+        self.ins(I::BitAnd);
+
+        return self;
+    }
+
+    pub fn push_bit_or(&mut self)->&mut Self {
+        self.ins(I::BitOr);
+
+        return self;
+    }
+
+    pub fn push_bit_xor(&mut self)->&mut Self {
+        self.ins(I::BitXor);
+
+        return self;
+    }
+
+    pub fn push_shl(&mut self)->&mut Self {
+        self.ins(I::Shl);
+
+        return self;
+    }
+
+    pub fn push_shr(&mut self)->&mut Self {
+        self.ins(I::Shr);
+
+        return self;
+    }
+
+    pub fn push_bit_not(&mut self)->&mut Self {
+        self.ins(I::BitNot);
+
+        return self;
+    }
+
+    pub fn push_in(&mut self)->&mut Self {
+        self.ins(I::In);
+
+        return self;
+    }
+
+    pub fn push_equal(&mut self)->&mut Self {
+        self.ins(I::Equal);
+
+        return self;
+    }
+
+    pub fn push_not_equal(&mut self)->&mut Self {
+        self.ins(I::NotEqual);
+
+        return self;
+    }
+
+    pub fn push_less(&mut self)->&mut Self {
+        self.ins(I::Less);
+
+        return self;
+    }
+
+    pub fn push_greater(&mut self)->&mut Self {
+        self.ins(I::Greater);
+
+        return self;
+    }
+
+    pub fn push_less_equal(&mut self)->&mut Self {
+        self.ins(I::LessEqual);
+
+        return self;
+    }
+
+    pub fn push_greater_equal(&mut self)->&mut Self {
+        self.ins(I::GreaterEqual);
+
+        return self;
+    }
+
+    pub fn push_index(&mut self)->&mut Self {
+        self.ins(I::Index);
+
+        return self;
+    }
+
+    /// Finish the module, closing the current span.
+    pub fn finish(mut self, id: ModuleId, name: &str)->Module<'_> {
+        let end = self.code.len();
+        self.spans.push(BytecodeSpan {
+            source_span: self.current_source_span,
+            instruction_span: self.current_code_span_start..end,
+        });
+
+        return Module {
+            id,
+            name: Cow::Borrowed(name),
+            code: self.code,
+            constants: self.constants,
+            spans: self.spans,
+        };
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_are_deduplicated() {
+        let mut builder = ModuleBuilder::new(0..0);
+        builder.register_constant(Constant::Float(0.0));
+        builder.register_constant(Constant::Float(f64::NAN));
+        builder.register_constant(Constant::String("a".to_string()));
+
+        // `-0.0` has different bits than `0.0`, so it is a new constant
+        let id = builder.register_constant(Constant::Float(-0.0));
+        assert!(matches!(id, ConstantId::One(3)));
+
+        let id = builder.register_constant(Constant::Float(f64::NAN));
+        assert!(matches!(id, ConstantId::One(1)));
+        let id = builder.register_constant(Constant::String("a".to_string()));
+        assert!(matches!(id, ConstantId::One(2)));
+
+        assert_eq!(builder.constants.len(), 4);
+    }
+
+    #[test]
+    fn constant_id_boundaries() {
+        let id = |index|ModuleBuilder::constant_id(index);
+
+        assert!(matches!(id(255), ConstantId::One(255)));
+        assert!(matches!(id(256), ConstantId::Two(256)));
+        assert!(matches!(id(0xffff), ConstantId::Two(0xffff)));
+        assert!(matches!(id(0x10000), ConstantId::Three(0x10000)));
+        assert!(matches!(id(U24_MAX), ConstantId::Three(0xffffff)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_many_constants() {
+        ModuleBuilder::constant_id(U24_MAX + 1);
+    }
+
+    #[test]
+    fn u24_constant_ids() {
+        let mut builder = ModuleBuilder::new(0..0);
+        builder.push_const(ConstantId::Three(0x030201));
+        assert_eq!(builder.code, [I::Constant3.into(), 1, 2, 3]);
+
+        // the VM reads the same 3 bytes back
+        let mut builder = ModuleBuilder::new(0..0);
+        for i in 0..=0x10000 {
+            builder.register_constant(Constant::Integer(i));
+        }
+        builder
+            .push_const(ConstantId::Three(0x10000))
+            .push_ret_val();
+
+        let mut program = Program::new(vec![builder.finish(ModuleId(0), "test")], ModuleId(0));
+        program.run().unwrap();
+        assert_eq!(program.stack, [Value::Integer(0x10000)]);
+    }
+
+    #[test]
+    fn backward_jump() {
+        // count local 0 up to 3 in a loop
+        let mut builder = ModuleBuilder::new(0..0);
+        builder.push_constant(Constant::Integer(0));
+
+        let start = builder.current_offset();
+        let exit = builder
+            .push_get_local(0)
+            .push_constant(Constant::Integer(3))
+            .push_less()
+            .push_jump_if_false();
+        builder
+            .push_get_local(0)
+            .push_constant(Constant::Integer(1))
+            .push_add()
+            .push_set_local(0)
+            .emit_jump_to(start)
+            .unwrap();
+        builder
+            .patch_jump(exit)
+            .unwrap()
+            .push_get_local(0)
+            .push_ret_val();
+
+        let mut program = Program::new(vec![builder.finish(ModuleId(0), "test")], ModuleId(0));
+        program.run().unwrap();
+        assert_eq!(program.stack, [Value::Integer(3)]);
+    }
+
+    #[test]
+    fn backward_jump_too_long() {
+        let mut builder = ModuleBuilder::new(0..0);
+        let start = builder.current_offset();
+        for _ in 0..(i16::MAX as usize + 1) {
+            builder.push_nop();
+        }
+
+        let err = builder.emit_jump_to(start).err().unwrap();
+        assert!(matches!(err.err_type(), ErrorType::CompileUnsupported(_)));
+    }
+
+    #[test]
+    fn forward_jump_too_long() {
+        let mut builder = ModuleBuilder::new(0..0);
+        let patch = builder.push_jump();
+        for _ in 0..(i16::MAX as usize + 1) {
+            builder.push_nop();
+        }
+
+        let err = builder.patch_jump(patch).err().unwrap();
+        assert!(matches!(err.err_type(), ErrorType::CompileUnsupported(_)));
+    }
 }
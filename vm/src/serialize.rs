@@ -0,0 +1,425 @@
+//! Saving and loading compiled [`Program`]s. The format is little endian:
+//!
+//! - the magic bytes `TLC\0` and a `u16` format version
+//! - the global module id as a `u64`, and a `u32` module count
+//! - each module: its id (`u64`), name, code, constants, and spans
+//!
+//! Strings and byte arrays are a `u32` length followed by the bytes, and lists are a `u32` count
+//! followed by the items. Constants are a tag byte followed by their value.
+
+
+use std::{
+    borrow::Cow,
+    fs,
+    path::Path,
+};
+use test_lang_common::{
+    error::*,
+    Span,
+};
+use crate::*;
+
+
+pub const MAGIC: &[u8; 4] = b"TLC\0";
+/// Bump this whenever the format or the bytecode changes
+pub const FORMAT_VERSION: u16 = 5;
+
+
+const TAG_INTEGER: u8 = 0;
+const TAG_FLOAT: u8 = 1;
+const TAG_BOOL: u8 = 2;
+const TAG_STRING: u8 = 3;
+const TAG_MODULE_ID: u8 = 4;
+
+
+fn invalid(reason: impl Into<String>)->Error {
+    Error::new(0..0, ErrorType::InvalidProgramFile(reason.into()))
+}
+
+
+impl<'a> Program<'a> {
+    /// Write the program to a byte buffer
+    pub fn to_bytes(&self)->Vec<u8> {
+        let mut out = Vec::new();
+
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        write_u64(&mut out, self.global_module.0);
+        write_u32(&mut out, self.modules.len());
+
+        for module in self.modules.iter() {
+            module.write_to(&mut out);
+        }
+
+        return out;
+    }
+
+    /// Read a program written by [`Program::to_bytes`]. Corrupt data or a different format
+    /// version returns an `InvalidProgramFile` error.
+    pub fn from_bytes(bytes: &[u8])->Result<Program<'static>, Error> {
+        let mut reader = Reader {bytes, pos: 0};
+
+        if reader.bytes(4)? != MAGIC {
+            return Err(invalid("not a compiled program"));
+        }
+
+        let version = u16::from_le_bytes([reader.u8()?, reader.u8()?]);
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!("format version {version} is not supported, expected {FORMAT_VERSION}")));
+        }
+
+        let global_module = ModuleId(reader.u64()?);
+
+        let count = reader.u32()?;
+        let mut modules = Vec::new();
+        for _ in 0..count {
+            modules.push(Module::read_from(&mut reader)?);
+        }
+
+        if reader.pos != bytes.len() {
+            return Err(invalid("trailing data after the last module"));
+        }
+
+        // modules are indexed by their id, so they have to be in order
+        for (i, module) in modules.iter().enumerate() {
+            if module.id.0 != i {
+                return Err(invalid(format!("module {i} has the id {}", module.id.0)));
+            }
+        }
+        if global_module.0 >= modules.len() {
+            return Err(invalid("the global module does not exist"));
+        }
+
+        for module in modules.iter() {
+            module.validate(modules.len())?;
+        }
+
+        return Ok(Program::new(modules, global_module));
+    }
+
+    /// Save the program to `path`, usually with the `.tlc` extension
+    pub fn save<P: AsRef<Path>>(&self, path: P)->Result<(), Error> {
+        fs::write(path, self.to_bytes())
+            .map_err(|e|invalid(e.to_string()))
+    }
+
+    /// Load a program saved with [`Program::save`]
+    pub fn load<P: AsRef<Path>>(path: P)->Result<Program<'static>, Error> {
+        let bytes = fs::read(path)
+            .map_err(|e|invalid(e.to_string()))?;
+
+        Program::from_bytes(&bytes)
+    }
+}
+
+impl<'a> Module<'a> {
+    fn write_to(&self, out: &mut Vec<u8>) {
+        write_u64(out, self.id.0);
+        write_bytes(out, self.name.as_bytes());
+        write_bytes(out, &self.code);
+
+        write_u32(out, self.constants.len());
+        for constant in self.constants.iter() {
+            match constant {
+                Constant::Integer(i)=>{
+                    out.push(TAG_INTEGER);
+                    out.extend_from_slice(&i.to_le_bytes());
+                },
+                Constant::Float(f)=>{
+                    out.push(TAG_FLOAT);
+                    out.extend_from_slice(&f.to_bits().to_le_bytes());
+                },
+                Constant::Bool(b)=>{
+                    out.push(TAG_BOOL);
+                    out.push(*b as u8);
+                },
+                Constant::String(s)=>{
+                    out.push(TAG_STRING);
+                    write_bytes(out, s.as_bytes());
+                },
+                Constant::ModuleId(id)=>{
+                    out.push(TAG_MODULE_ID);
+                    write_u64(out, *id);
+                },
+            }
+        }
+
+        write_u32(out, self.spans.len());
+        for span in self.spans.iter() {
+            write_span(out, &span.instruction_span);
+            write_span(out, &span.source_span);
+        }
+    }
+
+    fn read_from(reader: &mut Reader)->Result<Module<'static>, Error> {
+        let id = ModuleId(reader.u64()?);
+        let name = reader.string()?;
+        let code = reader.byte_array()?.to_vec();
+
+        let count = reader.u32()?;
+        let mut constants = Vec::new();
+        for _ in 0..count {
+            let constant = match reader.u8()? {
+                TAG_INTEGER=>Constant::Integer(reader.u64()? as i64),
+                TAG_FLOAT=>Constant::Float(f64::from_bits(reader.u64()? as u64)),
+                TAG_BOOL=>match reader.u8()? {
+                    0=>Constant::Bool(false),
+                    1=>Constant::Bool(true),
+                    b=>return Err(invalid(format!("invalid bool {b}"))),
+                },
+                TAG_STRING=>Constant::String(reader.string()?),
+                TAG_MODULE_ID=>Constant::ModuleId(reader.u64()?),
+                tag=>return Err(invalid(format!("invalid constant tag {tag}"))),
+            };
+            constants.push(constant);
+        }
+
+        let count = reader.u32()?;
+        let mut spans = Vec::new();
+        for _ in 0..count {
+            let instruction_span = reader.span()?;
+            let source_span = reader.span()?;
+            spans.push(BytecodeSpan::new(instruction_span, source_span));
+        }
+
+        return Ok(Module {
+            id,
+            name: Cow::Owned(name),
+            code,
+            constants,
+            spans,
+        });
+    }
+
+    /// Make sure the code only uses valid opcodes, constants, jump targets, and module ids so
+    /// running a loaded module can't panic on an out of bounds index.
+    fn validate(&self, module_count: usize)->Result<(), Error> {
+        use Instruction as I;
+
+        let mut ip = 0;
+        while ip < self.code.len() {
+            let (ins, operand_bytes) = self.decode_at(ip)
+                .map_err(|e|invalid(format!("module `{}`: {}", self.name, e.err_type())))?;
+
+            let operands = &self.code[ip + 1..ip + 1 + operand_bytes];
+            let constant = match ins {
+                I::Constant=>Some(operands[0] as usize),
+                I::Constant2=>Some(u16::from_le_bytes([operands[0], operands[1]]) as usize),
+                I::Constant3=>Some(u32::from_le_bytes([operands[0], operands[1], operands[2], 0]) as usize),
+                _=>None,
+            };
+            if let Some(index) = constant {
+                if index >= self.constants.len() {
+                    return Err(invalid(format!("module `{}`: constant {index} does not exist", self.name)));
+                }
+            }
+
+            if let I::Jump|I::JumpIfFalse|I::JumpIfTrue = ins {
+                let target = self.read_jump_target(&mut (ip + 1));
+                if target > self.code.len() {
+                    return Err(invalid(format!("module `{}`: jump target {target} is out of bounds", self.name)));
+                }
+            }
+
+            ip += 1 + operand_bytes;
+        }
+
+        for constant in self.constants.iter() {
+            if let Constant::ModuleId(id) = constant {
+                if *id >= module_count {
+                    return Err(invalid(format!("module `{}`: module {id} does not exist", self.name)));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+
+fn write_u32(out: &mut Vec<u8>, n: usize) {
+    let n = u32::try_from(n).expect("Length does not fit in a u32");
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_u64(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u64).to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_u32(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn write_span(out: &mut Vec<u8>, span: &Span) {
+    write_u64(out, span.start);
+    write_u64(out, span.end);
+}
+
+
+/// Reads values from a byte buffer, returning an error instead of panicking when it runs out
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize)->Result<&'a [u8], Error> {
+        let end = self.pos
+            .checked_add(len)
+            .filter(|end|*end <= self.bytes.len())
+            .ok_or_else(||invalid("unexpected end of file"))?;
+
+        let out = &self.bytes[self.pos..end];
+        self.pos = end;
+
+        return Ok(out);
+    }
+
+    fn u8(&mut self)->Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self)->Result<usize, Error> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    fn u64(&mut self)->Result<usize, Error> {
+        let bytes = self.bytes(8)?;
+        usize::try_from(u64::from_le_bytes(bytes.try_into().unwrap()))
+            .map_err(|_|invalid("number does not fit in a usize"))
+    }
+
+    fn byte_array(&mut self)->Result<&'a [u8], Error> {
+        let len = self.u32()?;
+        self.bytes(len)
+    }
+
+    fn string(&mut self)->Result<String, Error> {
+        let bytes = self.byte_array()?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_|invalid("invalid UTF-8 in a string"))
+    }
+
+    fn span(&mut self)->Result<Span, Error> {
+        Ok(self.u64()?..self.u64()?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program()->Program<'static> {
+        let mut builder = module_builder::ModuleBuilder::new(0..3);
+        builder
+            .push_constant(Constant::Integer(-7))
+            .push_constant(Constant::Float(1.5))
+            .set_span(4..9)
+            .push_constant(Constant::Bool(true))
+            .push_constant(Constant::String("héllo".into()))
+            .push_constant(Constant::ModuleId(1))
+            .push_call(0)
+            .push_ret();
+        let main = builder.finish(ModuleId(0), "main");
+
+        let mut builder = module_builder::ModuleBuilder::new(0..0);
+        builder.push_ret();
+        let function = builder.finish(ModuleId(1), "function");
+
+        return Program::new(vec![main, function], ModuleId(0));
+    }
+
+    /// the reason of an `InvalidProgramFile` error
+    fn reason(bytes: &[u8])->String {
+        match Program::from_bytes(bytes) {
+            Err(e)=>match e.err_type() {
+                ErrorType::InvalidProgramFile(reason)=>reason.clone(),
+                other=>panic!("Expected an invalid program file, found {other:?}"),
+            },
+            Ok(_)=>panic!("Expected an error"),
+        }
+    }
+
+    #[test]
+    fn format_version() {
+        // changing the format has to bump the version, and this test with it
+        assert_eq!(FORMAT_VERSION, 5);
+        assert_eq!(&program().to_bytes()[..6], b"TLC\0\x05\x00");
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = program();
+        let path = std::env::temp_dir().join(format!("test_lang_round_trip_{}.tlc", std::process::id()));
+        program.save(&path).unwrap();
+        let loaded = Program::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.global_module, program.global_module);
+        assert_eq!(loaded.modules.len(), 2);
+        for (loaded, module) in loaded.modules.iter().zip(program.modules.iter()) {
+            assert_eq!(loaded.id, module.id);
+            assert_eq!(loaded.name, module.name);
+            assert_eq!(loaded.code, module.code);
+            assert_eq!(loaded.constants, module.constants);
+            assert_eq!(loaded.spans.len(), module.spans.len());
+            for (a, b) in loaded.spans.iter().zip(module.spans.iter()) {
+                assert_eq!(a.instruction_span, b.instruction_span);
+                assert_eq!(a.source_span, b.source_span);
+            }
+        }
+        assert_eq!(loaded.to_bytes(), program.to_bytes());
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut bytes = program().to_bytes();
+        bytes[0] = b'X';
+        assert_eq!(reason(&bytes), "not a compiled program");
+
+        let mut bytes = program().to_bytes();
+        bytes[4] = 4;
+        assert_eq!(reason(&bytes), "format version 4 is not supported, expected 5");
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = program().to_bytes();
+        for len in 0..bytes.len() {
+            assert_eq!(reason(&bytes[..len]), "unexpected end of file");
+        }
+
+        let mut bytes = bytes;
+        bytes.push(0);
+        assert_eq!(reason(&bytes), "trailing data after the last module");
+    }
+
+    #[test]
+    fn bad_constant_tag() {
+        let mut bytes = program().to_bytes();
+        // magic, version, global module, module count, module id, name, code, constant count
+        let code_len = program().modules[0].code.len();
+        let tag = 4 + 2 + 8 + 4 + 8 + (4 + 4) + (4 + code_len) + 4;
+        assert_eq!(bytes[tag], TAG_INTEGER);
+
+        bytes[tag] = 9;
+        assert_eq!(reason(&bytes), "invalid constant tag 9");
+    }
+
+    #[test]
+    fn bad_constant_index() {
+        let module = Module {
+            id: ModuleId(0),
+            name: Cow::Borrowed("main"),
+            code: vec![Instruction::Constant.into(), 3],
+            constants: vec![Constant::Integer(0)],
+            spans: Vec::new(),
+        };
+        let bytes = Program::new(vec![module], ModuleId(0)).to_bytes();
+
+        assert_eq!(reason(&bytes), "module `main`: constant 3 does not exist");
+    }
+}